@@ -1,19 +1,25 @@
 use cg_util::geometry::Geometry;
+use cg_util::geometry::NormalWeighting;
 use cg_util::load::GpuObjectHandle;
 use cg_util::load::LoadOnGpu;
+use cg_util::lod::LodChain;
 use cg_util::main_loop;
 use cg_util::main_loop::State;
 use cg_util::manifold;
 use cg_util::navigator::Navigator;
 use cg_util::prelude::Coord3d;
 use cg_util::prelude::Trans4d;
+use cg_util::raycast;
+use cg_util::raycast::Bvh;
 use cg_util::render;
 use cg_util::shaders;
 use cg_util::solids;
 use cg_util::transform;
 use cgmath::Deg;
+use cgmath::SquareMatrix;
 use glium::backend::glutin::Display;
 use glium::glutin::Event;
+use glium::glutin::WindowEvent;
 use glium::index::NoIndices;
 use glium::Frame;
 use glium::Program;
@@ -22,9 +28,15 @@ use glium::Surface;
 type ClockElement = (Trans4d, Coord3d);
 
 struct ClockState {
-    center: GpuObjectHandle<Geometry>,
-    hour_marker: GpuObjectHandle<Geometry>,
+    center: LodChain<GpuObjectHandle<Geometry>>,
+    noon_marker: GpuObjectHandle<Geometry>,
+    hour_markers: GpuObjectHandle<Geometry>,
     hand: GpuObjectHandle<Geometry>,
+    center_bvh: Bvh,
+    noon_marker_bvh: Bvh,
+    hour_markers_bvh: Bvh,
+    hand_bvh: Bvh,
+    window_size: Option<(f64, f64)>,
     shaders: Program,
     navigator: Navigator,
     perspective: Trans4d,
@@ -36,10 +48,28 @@ fn main() {
 
 impl State for ClockState {
     fn init(display: &Display) -> Self {
+        let center = LodChain::from_manifold(manifold::sphere(180, 90), 4, 0.5).map(|mut level| {
+            level.smooth_normals(Deg(30.0).into(), NormalWeighting::Angle);
+            level.load(display)
+        });
+        let noon_marker = solids::cube().transformed(&get_hour_marker_transform(0, 0.05));
+        let hour_markers = solids::cube().instanced(&get_hour_marker_transforms());
+        let hand = solids::cube();
+
         Self {
-            center: Geometry::from_manifold(manifold::sphere(180, 90)).load(display),
-            hour_marker: solids::cube().load(display),
-            hand: solids::cube().load(display),
+            // The coarsest level is accurate enough to pick the center.
+            center_bvh: Bvh::new(&center.levels.last().unwrap().object.loaded_object),
+            noon_marker_bvh: Bvh::new(&noon_marker),
+            hour_markers_bvh: Bvh::new(&hour_markers),
+            hand_bvh: Bvh::new(&hand),
+            window_size: display
+                .gl_window()
+                .get_inner_size()
+                .map(|size| (size.width, size.height)),
+            center,
+            noon_marker: noon_marker.load(display),
+            hour_markers: hour_markers.load(display),
+            hand: hand.load(display),
             shaders: Program::from_source(
                 display,
                 include_str!("../shaders/vertex.glsl"),
@@ -53,6 +83,13 @@ impl State for ClockState {
     }
 
     fn process_event(&mut self, event: Event) {
+        if let Event::WindowEvent {
+            event: WindowEvent::Resized(size),
+            ..
+        } = event
+        {
+            self.window_size = Some((size.width, size.height));
+        }
         self.navigator.handle_event(event)
     }
 
@@ -63,15 +100,37 @@ impl State for ClockState {
         );
 
         let mut scene = Vec::new();
-        scene.push((&self.center, get_center_transform()));
-        for transform in get_clock_face_transforms() {
-            scene.push((&self.hour_marker, transform));
-        }
-        scene.push((&self.hand, get_second_hand_transform()));
-        scene.push((&self.hand, get_minute_hand_transform()));
-        scene.push((&self.hand, get_hour_hand_transform()));
-
-        for &(ref loaded, ref clock_element) in &scene {
+        let center = get_center_transform();
+        let center_level = self.center.select_for(&(scene_transform * center.0));
+        scene.push((center_level, &self.center_bvh, center));
+        scene.push((&self.noon_marker, &self.noon_marker_bvh, get_noon_marker()));
+        scene.push((
+            &self.hour_markers,
+            &self.hour_markers_bvh,
+            get_hour_markers(),
+        ));
+        scene.push((&self.hand, &self.hand_bvh, get_second_hand_transform()));
+        scene.push((&self.hand, &self.hand_bvh, get_minute_hand_transform()));
+        scene.push((&self.hand, &self.hand_bvh, get_hour_hand_transform()));
+
+        let hovered_element = self.window_size.and_then(|window_size| {
+            let mouse_ray = transform::cursor_ray(
+                self.navigator.mouse_position(),
+                window_size,
+                &scene_transform,
+            );
+            let objects = scene
+                .iter()
+                .map(|(_, bvh, clock_element)| (*bvh, &clock_element.0));
+            raycast::pick(&mouse_ray, objects).map(|(index, _)| index)
+        });
+
+        for (index, (loaded, _, clock_element)) in scene.iter().enumerate() {
+            let color = if hovered_element == Some(index) {
+                (clock_element.1 + Coord3d::new(1.0, 1.0, 0.0)) / 2.0
+            } else {
+                clock_element.1
+            };
             let object_transform = clock_element.0;
             let total_transform = scene_transform * object_transform;
             let normal_transform = transform::transform_normals(&object_transform);
@@ -79,13 +138,13 @@ impl State for ClockState {
                 total_transform,
                 normal_transform,
                 Coord3d::new(-1.0, -1.0, -1.0),
-                clock_element.1,
+                color,
             );
             let draw_parameters = render::default_draw_parameters();
             frame
                 .draw(
                     &loaded.buffer,
-                    &NoIndices(loaded.loaded_object.primitive_type),
+                    NoIndices(loaded.loaded_object.primitive_type),
                     &self.shaders,
                     &uniforms,
                     &draw_parameters,
@@ -99,27 +158,22 @@ fn get_center_transform() -> ClockElement {
     (Trans4d::from_scale(0.1), Coord3d::new(1.0, 1.0, 1.0))
 }
 
-fn get_clock_face_transforms() -> Vec<ClockElement> {
-    let mut transforms: Vec<ClockElement> = Vec::new();
-    transforms.push((
-        get_hour_marker_transform(0, 0.05),
-        Coord3d::new(1.0, 1.0, 1.0),
-    ));
+fn get_noon_marker() -> ClockElement {
+    (Trans4d::identity(), Coord3d::new(1.0, 1.0, 1.0))
+}
 
+fn get_hour_markers() -> ClockElement {
+    (Trans4d::identity(), Coord3d::new(0.33, 0.33, 0.33))
+}
+
+fn get_hour_marker_transforms() -> Vec<Trans4d> {
+    let mut transforms = Vec::new();
     for &hour in [3, 6, 9].iter() {
-        transforms.push((
-            get_hour_marker_transform(hour, 0.05),
-            Coord3d::new(0.33, 0.33, 0.33),
-        ));
+        transforms.push(get_hour_marker_transform(hour, 0.05));
     }
-
     for &hour in [1, 2, 4, 5, 7, 8, 10, 11].iter() {
-        transforms.push((
-            get_hour_marker_transform(hour, 0.025),
-            Coord3d::new(0.33, 0.33, 0.33),
-        ));
+        transforms.push(get_hour_marker_transform(hour, 0.025));
     }
-
     transforms
 }
 
@@ -0,0 +1,26 @@
+#version 130
+
+uniform vec3 light_direction;
+uniform sampler2D tex;
+uniform sampler2D normal_map;
+
+in vec2 fragment_tex_coord;
+in vec3 fragment_normal;
+in vec3 fragment_tangent;
+in float bitangent_sign;
+
+out vec4 color;
+
+void main(void) {
+	// As in MikkTSpace, the interpolated vectors are used without normalizing them first.
+	vec3 bitangent = bitangent_sign * cross(fragment_normal, fragment_tangent);
+	vec3 tangent_space_normal = texture(normal_map, fragment_tex_coord).xyz * 2 - 1;
+	vec3 normal = normalize(tangent_space_normal.x * fragment_tangent
+			+ tangent_space_normal.y * bitangent
+			+ tangent_space_normal.z * fragment_normal);
+	float dot_product = dot(normalize(light_direction), normal);
+	float shade = (1 - dot_product) / 2;
+	vec4 tex_color = texture(tex, fragment_tex_coord);
+	vec4 real_color = shade * tex_color;
+	color = real_color * real_color;
+}
@@ -0,0 +1,23 @@
+#version 130
+
+uniform mat4 position_transform;
+uniform mat3 normal_transform;
+uniform mat3 tangent_transform;
+
+in vec3 vertex_position;
+in vec3 vertex_normal;
+in vec2 tex_coord;
+in vec4 vertex_tangent;
+
+out vec2 fragment_tex_coord;
+out vec3 fragment_normal;
+out vec3 fragment_tangent;
+out float bitangent_sign;
+
+void main() {
+	gl_Position = position_transform * vec4(vertex_position, 1.0);
+	fragment_tex_coord = tex_coord;
+	fragment_normal = normal_transform * vertex_normal;
+	fragment_tangent = tangent_transform * vertex_tangent.xyz;
+	bitangent_sign = vertex_tangent.w;
+}
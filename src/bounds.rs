@@ -0,0 +1,367 @@
+use crate::geometry::Geometry;
+use crate::manifold::Manifold2d;
+use crate::prelude::*;
+use cgmath::EuclideanSpace;
+use cgmath::InnerSpace;
+use cgmath::Point3;
+use cgmath::Transform;
+
+/// An axis-aligned bounding box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Coord3d,
+    pub max: Coord3d,
+}
+
+impl Aabb {
+    /// Returns `None` if `points` is empty.
+    pub fn from_points<I: IntoIterator<Item = Coord3d>>(points: I) -> Option<Aabb> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut aabb = Aabb {
+            min: first,
+            max: first,
+        };
+        for point in points {
+            aabb.min = component_min(aabb.min, point);
+            aabb.max = component_max(aabb.max, point);
+        }
+        Some(aabb)
+    }
+
+    pub fn from_geometry<V: MeshVertex>(geometry: &Geometry<V>) -> Option<Aabb> {
+        Aabb::from_points(geometry.vertices.iter().map(MeshVertex::position))
+    }
+
+    pub fn from_manifold<M: Manifold2d<Coord = Coord3d>>(manifold: &M) -> Option<Aabb> {
+        Aabb::from_points(manifold_points(manifold))
+    }
+
+    /// Returns the smallest box containing all `boxes` or `None` if there are none.
+    pub fn union_all<I: IntoIterator<Item = Aabb>>(boxes: I) -> Option<Aabb> {
+        boxes.into_iter().fold(None, |union, aabb| match union {
+            Some(union) => Some(aabb.union(&union)),
+            None => Some(aabb),
+        })
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: component_min(self.min, other.min),
+            max: component_max(self.max, other.max),
+        }
+    }
+
+    pub fn center(&self) -> Coord3d {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn size(&self) -> Coord3d {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: Coord3d) -> bool {
+        (0..3).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+
+    pub fn corners(&self) -> [Coord3d; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Coord3d::new(min.x, min.y, min.z),
+            Coord3d::new(max.x, min.y, min.z),
+            Coord3d::new(min.x, max.y, min.z),
+            Coord3d::new(max.x, max.y, min.z),
+            Coord3d::new(min.x, min.y, max.z),
+            Coord3d::new(max.x, min.y, max.z),
+            Coord3d::new(min.x, max.y, max.z),
+            Coord3d::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// Returns the box enclosing the transformed corners of `self`. The result contains every
+    /// transformed point of `self` as long as `transform` is affine.
+    pub fn transformed(&self, transform: &Trans4d) -> Aabb {
+        let corners = self.corners();
+        let transformed_corners = corners
+            .iter()
+            .map(|&corner| transform.transform_point(Point3::from_vec(corner)).to_vec());
+        Aabb::from_points(transformed_corners).unwrap()
+    }
+
+    /// Returns a transform that uniformly scales and moves `self` into the cube from `-1` to `1`,
+    /// centered at the origin. Useful to normalize imported meshes.
+    pub fn normalizing_transform(&self) -> Trans4d {
+        let size = self.size();
+        let largest_extent = size.x.max(size.y).max(size.z);
+        let scale = if largest_extent > 0.0 {
+            2.0 / largest_extent
+        } else {
+            1.0
+        };
+        Trans4d::from_scale(scale) * Trans4d::from_translation(-self.center())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Coord3d,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Computes an enclosing sphere with Ritter's algorithm. The sphere is at most a few percent
+    /// larger than the minimal one. Points with non-finite coordinates are skipped. Returns `None`
+    /// if no finite point is left.
+    pub fn from_points<I: IntoIterator<Item = Coord3d>>(points: I) -> Option<BoundingSphere> {
+        let points = points
+            .into_iter()
+            .filter(|point| point.x.is_finite() && point.y.is_finite() && point.z.is_finite())
+            .collect::<Vec<_>>();
+        let first = *points.first()?;
+        let farthest_from = |origin: Coord3d| {
+            points
+                .iter()
+                .cloned()
+                .max_by(|a, b| {
+                    (a - origin)
+                        .magnitude2()
+                        .partial_cmp(&(b - origin).magnitude2())
+                        .unwrap()
+                })
+                .unwrap()
+        };
+        let a = farthest_from(first);
+        let b = farthest_from(a);
+
+        let mut sphere = BoundingSphere {
+            center: (a + b) / 2.0,
+            radius: (b - a).magnitude() / 2.0,
+        };
+        for &point in &points {
+            sphere = sphere.union(&BoundingSphere {
+                center: point,
+                radius: 0.0,
+            });
+        }
+        Some(sphere)
+    }
+
+    pub fn from_geometry<V: MeshVertex>(geometry: &Geometry<V>) -> Option<BoundingSphere> {
+        BoundingSphere::from_points(geometry.vertices.iter().map(MeshVertex::position))
+    }
+
+    pub fn from_manifold<M: Manifold2d<Coord = Coord3d>>(manifold: &M) -> Option<BoundingSphere> {
+        BoundingSphere::from_points(manifold_points(manifold))
+    }
+
+    /// Returns a sphere containing all `spheres` or `None` if there are none. The spheres are
+    /// merged one after another with `union`, so the result is not minimal in general and depends
+    /// on their order.
+    pub fn union_all<I: IntoIterator<Item = BoundingSphere>>(spheres: I) -> Option<BoundingSphere> {
+        spheres.into_iter().fold(None, |union, sphere| match union {
+            Some(union) => Some(sphere.union(&union)),
+            None => Some(sphere),
+        })
+    }
+
+    /// Returns the smallest sphere containing both `self` and `other`.
+    pub fn union(&self, other: &BoundingSphere) -> BoundingSphere {
+        let offset = other.center - self.center;
+        let distance = offset.magnitude();
+        if distance + other.radius <= self.radius {
+            *self
+        } else if distance + self.radius <= other.radius {
+            *other
+        } else {
+            let radius = (distance + self.radius + other.radius) / 2.0;
+            BoundingSphere {
+                center: self.center + offset * ((radius - self.radius) / distance),
+                radius,
+            }
+        }
+    }
+
+    pub fn contains(&self, point: Coord3d) -> bool {
+        (point - self.center).magnitude2() <= self.radius * self.radius
+    }
+
+    /// Returns a sphere containing the transformed sphere if `transform` is affine. The radius is
+    /// scaled by a bound of the largest stretch of `transform`, which is exact as long as the
+    /// transformed axes stay orthogonal, e.g. for rotations and nonuniform scales.
+    pub fn transformed(&self, transform: &Trans4d) -> BoundingSphere {
+        // By the Gershgorin circle theorem, the largest row sum of the Gram matrix of the axes
+        // bounds its largest eigenvalue, the square of the largest stretch.
+        let axes = [
+            transform[0].truncate(),
+            transform[1].truncate(),
+            transform[2].truncate(),
+        ];
+        let largest_scale = axes
+            .iter()
+            .map(|axis| axes.iter().map(|other| axis.dot(*other).abs()).sum::<f32>())
+            .fold(0.0, f32::max)
+            .sqrt();
+        let transformed_center = transform.transform_point(Point3::from_vec(self.center));
+        BoundingSphere {
+            center: transformed_center.to_vec(),
+            radius: self.radius * largest_scale,
+        }
+    }
+}
+
+impl From<Aabb> for BoundingSphere {
+    fn from(aabb: Aabb) -> Self {
+        BoundingSphere {
+            center: aabb.center(),
+            radius: aabb.size().magnitude() / 2.0,
+        }
+    }
+}
+
+fn component_min(a: Coord3d, b: Coord3d) -> Coord3d {
+    Coord3d::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+}
+
+fn component_max(a: Coord3d, b: Coord3d) -> Coord3d {
+    Coord3d::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+}
+
+fn manifold_points<M: Manifold2d<Coord = Coord3d>>(
+    manifold: &M,
+) -> impl Iterator<Item = Coord3d> + '_ {
+    (0..manifold.width()).flat_map(move |x| (0..manifold.height()).map(move |y| manifold.get(x, y)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Deg;
+    use std::f32;
+
+    fn aabb(min: (f32, f32, f32), max: (f32, f32, f32)) -> Aabb {
+        Aabb {
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+
+    fn assert_near(a: Coord3d, b: Coord3d) {
+        assert!((a - b).magnitude() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn aabb_contains_its_points() {
+        let points = [
+            Coord3d::new(1.0, -2.0, 0.5),
+            Coord3d::new(-1.0, 3.0, 0.0),
+            Coord3d::new(0.0, 0.0, 2.0),
+        ];
+        let aabb = Aabb::from_points(points.iter().cloned()).unwrap();
+
+        assert_eq!(aabb, self::aabb((-1.0, -2.0, 0.0), (1.0, 3.0, 2.0)));
+        assert!(points.iter().all(|&point| aabb.contains(point)));
+        assert!(!aabb.contains(Coord3d::new(0.0, 0.0, 2.1)));
+        assert_eq!(Aabb::from_points(None), None);
+    }
+
+    #[test]
+    fn aabb_union_encloses_both() {
+        let a = aabb((0.0, 0.0, 0.0), (1.0, 1.0, 1.0));
+        let b = aabb((2.0, -1.0, 0.5), (3.0, 0.5, 0.75));
+
+        let union = a.union(&b);
+        assert_eq!(union, aabb((0.0, -1.0, 0.0), (3.0, 1.0, 1.0)));
+        assert_eq!(Aabb::union_all(vec![a, b]), Some(union));
+        assert_eq!(Aabb::union_all(None), None);
+    }
+
+    #[test]
+    fn aabb_transform_encloses_rotated_corners() {
+        let cube = aabb((-1.0, -1.0, -1.0), (1.0, 1.0, 1.0));
+        let transform = Trans4d::from_translation(Coord3d::new(5.0, 0.0, 0.0))
+            * Trans4d::from_angle_z(Deg(45.0));
+
+        let transformed = cube.transformed(&transform);
+        let diagonal = f32::consts::SQRT_2;
+        assert_near(
+            transformed.min,
+            Coord3d::new(5.0 - diagonal, -diagonal, -1.0),
+        );
+        assert_near(transformed.max, Coord3d::new(5.0 + diagonal, diagonal, 1.0));
+    }
+
+    #[test]
+    fn sphere_contains_its_points() {
+        let points = (0..100)
+            .map(|index| {
+                let index = index as f32;
+                Coord3d::new((index * 0.7).sin(), (index * 1.3).cos(), index / 50.0 - 1.0)
+            })
+            .collect::<Vec<_>>();
+        let sphere = BoundingSphere::from_points(points.iter().cloned()).unwrap();
+
+        let slack = 1.0 + 1e-5;
+        for &point in &points {
+            assert!((point - sphere.center).magnitude() <= sphere.radius * slack);
+        }
+        let minimal_radius = 3.0f32.sqrt();
+        assert!(sphere.radius <= minimal_radius * 1.1);
+    }
+
+    #[test]
+    fn sphere_skips_non_finite_points() {
+        let points = vec![
+            Coord3d::new(f32::NAN, 0.0, 0.0),
+            Coord3d::new(-1.0, 0.0, 0.0),
+            Coord3d::new(0.0, f32::INFINITY, 0.0),
+            Coord3d::new(1.0, 0.0, 0.0),
+        ];
+        let sphere = BoundingSphere::from_points(points).unwrap();
+
+        assert_near(sphere.center, Coord3d::new(0.0, 0.0, 0.0));
+        assert_eq!(sphere.radius, 1.0);
+        assert_eq!(
+            BoundingSphere::from_points(vec![Coord3d::new(f32::NAN, 0.0, 0.0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn sphere_union_encloses_both() {
+        let a = BoundingSphere {
+            center: Coord3d::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let b = BoundingSphere {
+            center: Coord3d::new(4.0, 0.0, 0.0),
+            radius: 2.0,
+        };
+        let inner = BoundingSphere {
+            center: Coord3d::new(0.5, 0.0, 0.0),
+            radius: 0.25,
+        };
+
+        let union = a.union(&b);
+        assert_near(union.center, Coord3d::new(2.5, 0.0, 0.0));
+        assert_eq!(union.radius, 3.5);
+        assert_eq!(a.union(&inner), a);
+        assert_eq!(inner.union(&a), a);
+        assert!(union.contains(Coord3d::new(-1.0, 0.0, 0.0)));
+        assert!(union.contains(Coord3d::new(6.0, 0.0, 0.0)));
+        assert!(!union.contains(Coord3d::new(6.1, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn sphere_transform_scales_radius() {
+        let sphere = BoundingSphere {
+            center: Coord3d::new(1.0, 0.0, 0.0),
+            radius: 2.0,
+        };
+        let transform =
+            Trans4d::from_angle_y(Deg(90.0)) * Trans4d::from_nonuniform_scale(1.0, 3.0, 0.5);
+
+        let transformed = sphere.transformed(&transform);
+        assert_near(transformed.center, Coord3d::new(0.0, 0.0, -1.0));
+        assert!((transformed.radius - 6.0).abs() < 1e-5);
+    }
+}
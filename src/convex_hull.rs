@@ -0,0 +1,500 @@
+use crate::geometry::Geometry;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use cgmath::Vector3;
+use glium::index::PrimitiveType;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// Points closer to a plane than this fraction of the extent of the point set count as lying on
+/// the plane. The input has single precision, so smaller distances are rounding noise.
+const RELATIVE_TOLERANCE: f64 = 1e-6;
+
+type Coord3dF64 = Vector3<f64>;
+
+#[derive(Debug)]
+pub enum ConvexHullError {
+    NonFinitePoint {
+        index: usize,
+    },
+    /// There are less than two distinct points.
+    TooFewPoints,
+    /// All points lie on a line.
+    Collinear,
+}
+
+impl fmt::Display for ConvexHullError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConvexHullError::NonFinitePoint { index } => {
+                write!(f, "Point {} has non-finite coordinates", index)
+            }
+            ConvexHullError::TooFewPoints => write!(f, "Less than two distinct points"),
+            ConvexHullError::Collinear => write!(f, "All points lie on a line"),
+        }
+    }
+}
+
+impl Error for ConvexHullError {}
+
+/// Computes the convex hull of `points` with the quickhull algorithm. The result is a closed
+/// triangle list with outward-facing flat normals whose corners are taken from `points`.
+///
+/// Duplicate points and points lying on the hull within a small tolerance do not become hull
+/// vertices, so that coplanar points do not produce sliver triangles. If all points lie in a
+/// plane, the hull is a flat convex polygon whose triangles are included facing both sides, so that
+/// it is visible with back-face culling.
+pub fn convex_hull<I: IntoIterator<Item = Coord3d>>(
+    points: I,
+) -> Result<Geometry, ConvexHullError> {
+    let points = points.into_iter().collect::<Vec<_>>();
+    if let Some(index) = points
+        .iter()
+        .position(|point| !(point.x.is_finite() && point.y.is_finite() && point.z.is_finite()))
+    {
+        return Err(ConvexHullError::NonFinitePoint { index });
+    }
+
+    let positions = points
+        .iter()
+        .map(|point| point.cast::<f64>().unwrap())
+        .collect::<Vec<_>>();
+    let mut geometry = Geometry::new(PrimitiveType::TrianglesList);
+    match Hull::start(&positions)? {
+        Start::Solid(mut hull) => {
+            hull.expand();
+            for face in hull.faces.iter().filter(|face| face.is_alive) {
+                let [a, b, c] = face.vertices;
+                geometry.push_triangle(points[a], points[b], points[c]);
+            }
+        }
+        Start::Flat(polygon) => {
+            for corners in polygon[1..].windows(2) {
+                let (a, b, c) = (points[polygon[0]], points[corners[0]], points[corners[1]]);
+                geometry.push_triangle(a, b, c);
+                geometry.push_triangle(a, c, b);
+            }
+        }
+    }
+    Ok(geometry)
+}
+
+enum Start<'a> {
+    /// The initial tetrahedron.
+    Solid(Hull<'a>),
+    /// All points lie in a plane. Contains the corners of their convex hull in order.
+    Flat(Vec<usize>),
+}
+
+struct Hull<'a> {
+    positions: &'a [Coord3dF64],
+    tolerance: f64,
+    faces: Vec<Face>,
+    /// Maps every directed edge of a living face to that face.
+    edges: HashMap<(usize, usize), usize>,
+}
+
+struct Face {
+    /// Counterclockwise when seen from outside.
+    vertices: [usize; 3],
+    normal: Coord3dF64,
+    offset: f64,
+    /// The points in front of the face that have not been assigned to another face.
+    outside: Vec<usize>,
+    is_alive: bool,
+}
+
+impl<'a> Hull<'a> {
+    /// Creates the initial tetrahedron and assigns all points to its faces, or computes the flat
+    /// hull if there is no tetrahedron.
+    fn start(positions: &'a [Coord3dF64]) -> Result<Start<'a>, ConvexHullError> {
+        if positions.is_empty() {
+            return Err(ConvexHullError::TooFewPoints);
+        }
+
+        let mut extremes = [0; 6];
+        for (index, position) in positions.iter().enumerate() {
+            for axis in 0..3 {
+                if position[axis] < positions[extremes[2 * axis]][axis] {
+                    extremes[2 * axis] = index;
+                }
+                if position[axis] > positions[extremes[2 * axis + 1]][axis] {
+                    extremes[2 * axis + 1] = index;
+                }
+            }
+        }
+        let extent = (0..3)
+            .map(|axis| {
+                positions[extremes[2 * axis + 1]][axis] - positions[extremes[2 * axis]][axis]
+            })
+            .fold(0.0, f64::max);
+        let tolerance = RELATIVE_TOLERANCE * extent;
+
+        // The first edge connects the pair of extreme points farthest apart.
+        let mut a = extremes[0];
+        let mut b = extremes[0];
+        for &first in &extremes {
+            for &second in &extremes {
+                if (positions[second] - positions[first]).magnitude2()
+                    > (positions[b] - positions[a]).magnitude2()
+                {
+                    a = first;
+                    b = second;
+                }
+            }
+        }
+        if (positions[b] - positions[a]).magnitude() <= tolerance {
+            return Err(ConvexHullError::TooFewPoints);
+        }
+
+        let line_direction = (positions[b] - positions[a]).normalize();
+        let line_distance = |index: usize| {
+            let offset = positions[index] - positions[a];
+            (offset - offset.dot(line_direction) * line_direction).magnitude()
+        };
+        let c = farthest(0..positions.len(), line_distance).unwrap();
+        if line_distance(c) <= tolerance {
+            return Err(ConvexHullError::Collinear);
+        }
+
+        let plane_normal = (positions[b] - positions[a])
+            .cross(positions[c] - positions[a])
+            .normalize();
+        let plane_distance = |index: usize| (positions[index] - positions[a]).dot(plane_normal);
+        let d = farthest(0..positions.len(), |index| plane_distance(index).abs()).unwrap();
+        if plane_distance(d).abs() <= tolerance {
+            let axes = (line_direction, plane_normal.cross(line_direction));
+            return Ok(Start::Flat(flat_hull(positions, a, axes, tolerance)));
+        }
+
+        let mut hull = Hull {
+            positions,
+            tolerance,
+            faces: Vec::new(),
+            edges: HashMap::new(),
+        };
+        // Orient the base triangle away from the apex.
+        let (b, c) = if plane_distance(d) > 0.0 {
+            (c, b)
+        } else {
+            (b, c)
+        };
+        for &vertices in [[a, b, c], [a, d, b], [b, d, c], [c, d, a]].iter() {
+            hull.add_face(vertices);
+        }
+
+        let all_faces = (0..4).collect::<Vec<_>>();
+        let initial = [a, b, c, d];
+        hull.assign_points(
+            (0..positions.len()).filter(|index| !initial.contains(index)),
+            &all_faces,
+        );
+        Ok(Start::Solid(hull))
+    }
+
+    /// Adds the farthest outside point of some face to the hull until no outside points are left.
+    fn expand(&mut self) {
+        let mut pending = (0..self.faces.len()).collect::<Vec<_>>();
+        while let Some(face) = pending.pop() {
+            if !self.faces[face].is_alive || self.faces[face].outside.is_empty() {
+                continue;
+            }
+            let eye = farthest(self.faces[face].outside.iter().cloned(), |index| {
+                self.distance(face, index)
+            })
+            .unwrap();
+
+            let (visible, horizon) = self.visible_faces(face, eye);
+
+            let mut orphans = Vec::new();
+            for &visible_face in &visible {
+                self.faces[visible_face].is_alive = false;
+                orphans.append(&mut self.faces[visible_face].outside);
+                let [a, b, c] = self.faces[visible_face].vertices;
+                for &edge in [(a, b), (b, c), (c, a)].iter() {
+                    self.edges.remove(&edge);
+                }
+            }
+
+            let new_faces = horizon
+                .iter()
+                .map(|&(a, b)| self.add_face([a, b, eye]))
+                .collect::<Vec<_>>();
+            self.assign_points(
+                orphans.into_iter().filter(|&index| index != eye),
+                &new_faces,
+            );
+            pending.extend(new_faces);
+        }
+    }
+
+    /// Collects the faces that `eye` lies in front of, starting at `face` and walking over edges,
+    /// so that the visible region stays connected even if rounding errors say otherwise. Returns
+    /// these faces and the directed edges of the horizon as they appear in the visible faces.
+    fn visible_faces(&self, face: usize, eye: usize) -> (Vec<usize>, Vec<(usize, usize)>) {
+        let mut is_visible = HashMap::new();
+        is_visible.insert(face, true);
+        let mut visible = vec![face];
+        let mut horizon = Vec::new();
+        let mut stack = vec![face];
+        while let Some(face) = stack.pop() {
+            let [a, b, c] = self.faces[face].vertices;
+            for &(from, to) in [(a, b), (b, c), (c, a)].iter() {
+                let neighbor = self.edges[&(to, from)];
+                let neighbor_is_visible = *is_visible.entry(neighbor).or_insert_with(|| {
+                    let neighbor_is_visible = self.distance(neighbor, eye) > self.tolerance;
+                    if neighbor_is_visible {
+                        visible.push(neighbor);
+                        stack.push(neighbor);
+                    }
+                    neighbor_is_visible
+                });
+                if !neighbor_is_visible {
+                    horizon.push((from, to));
+                }
+            }
+        }
+        (visible, horizon)
+    }
+
+    fn add_face(&mut self, vertices: [usize; 3]) -> usize {
+        let [a, b, c] = vertices;
+        let (pa, pb, pc) = (self.positions[a], self.positions[b], self.positions[c]);
+        let normal = (pb - pa).cross(pc - pa);
+        let length = normal.magnitude();
+        // A zero normal keeps points from being assigned to a collapsed face.
+        let normal = if length > 0.0 {
+            normal / length
+        } else {
+            normal
+        };
+
+        let face = self.faces.len();
+        self.faces.push(Face {
+            vertices,
+            normal,
+            offset: normal.dot(pa),
+            outside: Vec::new(),
+            is_alive: true,
+        });
+        for &edge in [(a, b), (b, c), (c, a)].iter() {
+            self.edges.insert(edge, face);
+        }
+        face
+    }
+
+    /// Assigns every point to the first of `faces` it lies in front of. Other points are inside
+    /// the hull or on its surface and are dropped.
+    fn assign_points<I: IntoIterator<Item = usize>>(&mut self, points: I, faces: &[usize]) {
+        for index in points {
+            if let Some(&face) = faces
+                .iter()
+                .find(|&&face| self.distance(face, index) > self.tolerance)
+            {
+                self.faces[face].outside.push(index);
+            }
+        }
+    }
+
+    fn distance(&self, face: usize, index: usize) -> f64 {
+        let face = &self.faces[face];
+        face.normal.dot(self.positions[index]) - face.offset
+    }
+}
+
+/// Computes the convex hull of points lying in a plane with Andrew's monotone chain algorithm.
+/// The points are projected onto `axes` through `positions[origin]`. Returns the hull corners
+/// counterclockwise with respect to these axes, skipping corners within `tolerance` of the line
+/// through their neighbors.
+fn flat_hull(
+    positions: &[Coord3dF64],
+    origin: usize,
+    axes: (Coord3dF64, Coord3dF64),
+    tolerance: f64,
+) -> Vec<usize> {
+    let projected = positions
+        .iter()
+        .map(|&position| {
+            let offset = position - positions[origin];
+            (offset.dot(axes.0), offset.dot(axes.1))
+        })
+        .collect::<Vec<_>>();
+    let mut order = (0..positions.len()).collect::<Vec<_>>();
+    order.sort_by(|&i, &j| projected[i].partial_cmp(&projected[j]).unwrap());
+
+    // Whether `c` lies strictly to the left of the line from `a` to `b`.
+    let turns_left = |a: usize, b: usize, c: usize| {
+        let (ax, ay) = projected[a];
+        let (bx, by) = projected[b];
+        let (cx, cy) = projected[c];
+        let cross = (bx - ax) * (cy - ay) - (by - ay) * (cx - ax);
+        cross > tolerance * (bx - ax).hypot(by - ay)
+    };
+
+    let mut hull: Vec<usize> = Vec::new();
+    // The lower chain from left to right, then the upper chain from right to left.
+    for pass in [order.clone(), order.into_iter().rev().collect()].iter() {
+        let start = hull.len();
+        for &index in pass {
+            while hull.len() >= start + 2
+                && !turns_left(hull[hull.len() - 2], hull[hull.len() - 1], index)
+            {
+                hull.pop();
+            }
+            hull.push(index);
+        }
+        // The last point of each chain starts the other one.
+        hull.pop();
+    }
+    hull
+}
+
+fn farthest<I, F>(indices: I, distance: F) -> Option<usize>
+where
+    I: IntoIterator<Item = usize>,
+    F: Fn(usize) -> f64,
+{
+    indices
+        .into_iter()
+        .map(|index| (index, distance(index)))
+        .fold(
+            None,
+            |farthest: Option<(usize, f64)>, (index, distance)| match farthest {
+                Some((_, max_distance)) if max_distance >= distance => farthest,
+                _ => Some((index, distance)),
+            },
+        )
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation;
+
+    /// Deterministic points in the cube from -1 to 1.
+    fn random_points(count: usize) -> Vec<Coord3d> {
+        let mut state = 4321u32;
+        let mut random = move || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+        };
+        (0..count)
+            .map(|_| Coord3d::new(random(), random(), random()))
+            .collect()
+    }
+
+    fn cube_corners() -> Vec<Coord3d> {
+        let mut corners = Vec::new();
+        for &x in [-1.0, 1.0].iter() {
+            for &y in [-1.0, 1.0].iter() {
+                for &z in [-1.0, 1.0].iter() {
+                    corners.push(Coord3d::new(x, y, z));
+                }
+            }
+        }
+        corners
+    }
+
+    fn assert_contains_all(hull: &Geometry, points: &[Coord3d]) {
+        for triangle in hull.vertices.chunks_exact(3) {
+            let corner = triangle[0].position();
+            let normal = triangle[0].normal();
+            for &point in points {
+                assert!((point - corner).dot(normal) <= 1e-5);
+            }
+            for vertex in triangle {
+                assert!(points.contains(&vertex.position()));
+            }
+        }
+    }
+
+    #[test]
+    fn hull_contains_all_points() {
+        let points = random_points(500);
+        let hull = convex_hull(points.iter().cloned()).unwrap();
+        assert_contains_all(&hull, &points);
+        let report = validation::validate(&hull, 1e-6);
+        assert!(report.is_ok(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn duplicate_and_interior_points_are_dropped() {
+        let mut points = cube_corners();
+        points.extend(cube_corners());
+        points.extend(random_points(100).into_iter().map(|point| point * 0.9));
+        // On the surface, but not a corner.
+        points.push(Coord3d::new(1.0, 0.0, 0.0));
+        let hull = convex_hull(points.iter().cloned()).unwrap();
+        assert_eq!(hull.vertices.len(), 12 * 3);
+        assert_contains_all(&hull, &points);
+        let report = validation::validate(&hull, 1e-6);
+        assert!(report.is_ok(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn coplanar_points_give_a_double_sided_polygon() {
+        let mut points = vec![
+            Coord3d::new(-1.0, -1.0, 0.5),
+            Coord3d::new(1.0, -1.0, 0.5),
+            Coord3d::new(1.0, 1.0, 0.5),
+            Coord3d::new(-1.0, 1.0, 0.5),
+            // On an edge.
+            Coord3d::new(0.0, 1.0, 0.5),
+        ];
+        points.extend(
+            random_points(50)
+                .into_iter()
+                .map(|point| Coord3d::new(point.x, point.y, 0.5)),
+        );
+        points.extend(points.clone());
+        let hull = convex_hull(points.iter().cloned()).unwrap();
+        // Two triangles facing each side.
+        assert_eq!(hull.vertices.len(), 4 * 3);
+        let up = hull
+            .vertices
+            .iter()
+            .filter(|vertex| vertex.normal() == Coord3d::new(0.0, 0.0, 1.0))
+            .count();
+        let down = hull
+            .vertices
+            .iter()
+            .filter(|vertex| vertex.normal() == Coord3d::new(0.0, 0.0, -1.0))
+            .count();
+        assert_eq!((up, down), (6, 6));
+        assert_contains_all(&hull, &points);
+    }
+
+    #[test]
+    fn collinear_points_are_rejected() {
+        let points = (0..10).map(|i| Coord3d::new(i as f32, 2.0 * i as f32, 1.0));
+        assert!(matches!(
+            convex_hull(points),
+            Err(ConvexHullError::Collinear)
+        ));
+    }
+
+    #[test]
+    fn too_few_points_are_rejected() {
+        assert!(matches!(
+            convex_hull(Vec::new()),
+            Err(ConvexHullError::TooFewPoints)
+        ));
+        let point = Coord3d::new(1.0, 2.0, 3.0);
+        assert!(matches!(
+            convex_hull(vec![point; 5]),
+            Err(ConvexHullError::TooFewPoints)
+        ));
+    }
+
+    #[test]
+    fn non_finite_points_are_rejected() {
+        let mut points = cube_corners();
+        points.insert(3, Coord3d::new(0.0, f32::NAN, 0.0));
+        assert!(matches!(
+            convex_hull(points),
+            Err(ConvexHullError::NonFinitePoint { index: 3 })
+        ));
+    }
+}
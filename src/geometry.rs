@@ -1,25 +1,156 @@
+use crate::bounds::Aabb;
+use crate::isosurface;
+use crate::isosurface::ScalarField;
+use crate::isosurface::ScalarGrid;
 use crate::load::GpuObjectHandle;
 use crate::load::LoadOnGpu;
 use crate::manifold::Manifold2d;
+use crate::prelude::Coord2d;
 use crate::prelude::Coord3d;
+use crate::prelude::MeshVertex;
+use crate::prelude::TangentVertexAttribute;
+use crate::prelude::TexVertexAttribute;
+use crate::prelude::Trans4d;
 use crate::prelude::VertexAttribute;
+use crate::transform;
 use cgmath::InnerSpace;
+use cgmath::Rad;
+use cgmath::SquareMatrix;
 use glium::backend::glutin::Display;
 use glium::index::PrimitiveType;
+use glium::IndexBuffer;
+use glium::Vertex;
 use glium::VertexBuffer;
+use std::collections::HashMap;
 
-pub struct Geometry {
+/// Tolerance used to decide whether two triangle corners share the same position.
+pub(crate) const POSITION_TOLERANCE: f32 = 1e-5;
+
+/// Triangles whose height is below this fraction of their longest edge count as degenerate.
+const DEGENERACY_THRESHOLD: f32 = 1e-5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NormalWeighting {
+    /// Each face contributes proportionally to its interior angle at the shared vertex.
+    Angle,
+    /// Each face contributes proportionally to its area.
+    Area,
+}
+
+/// How `Geometry::from_manifold_with` splits the quads between neighboring grid lines into
+/// triangles. All strategies produce the same orientation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QuadTriangulation {
+    /// Two triangles per quad, split along the diagonal from grid point `(x, y)` to
+    /// `(x + 1, y + 1)`.
+    FixedDiagonal,
+    /// Like `FixedDiagonal` but the diagonal flips from quad to quad in a checkerboard pattern,
+    /// which avoids a visible grain in the shading.
+    AlternatingDiagonal,
+    /// Two triangles per quad, split along the shorter diagonal, which keeps the triangles closer
+    /// to the surface of curved manifolds.
+    ShortestDiagonal,
+    /// Four triangles per quad, meeting at the average of its corners. Symmetric but moves the
+    /// surface toward the center of non-planar quads.
+    CenterFan,
+}
+
+#[derive(Clone)]
+pub struct Geometry<V = VertexAttribute> {
     pub primitive_type: PrimitiveType,
-    pub vertices: Vec<VertexAttribute>,
+    pub vertices: Vec<V>,
 }
 
-impl Geometry {
-    pub fn new(primitive_type: PrimitiveType) -> Geometry {
+impl<V> Geometry<V> {
+    pub fn empty(primitive_type: PrimitiveType) -> Geometry<V> {
         Geometry {
             primitive_type,
             vertices: Vec::new(),
         }
     }
+}
+
+impl<V: MeshVertex> Geometry<V> {
+    /// Applies `transform` to all positions and the corresponding normal transform to all
+    /// normals. Triangle lists are rewound if `transform` mirrors them, so that the winding order
+    /// still agrees with the normals.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `transform` is not invertible.
+    pub fn transform(&mut self, transform: &Trans4d) {
+        let normal_transform = transform::transform_normals(transform);
+        for vertex in &mut self.vertices {
+            *vertex = vertex.transformed(transform, &normal_transform);
+        }
+        if self.primitive_type == PrimitiveType::TrianglesList && transform.determinant() < 0.0 {
+            for triangle in self.vertices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+    }
+
+    pub fn transformed(&self, transform: &Trans4d) -> Geometry<V> {
+        let mut geometry = self.clone();
+        geometry.transform(transform);
+        geometry
+    }
+
+    /// Appends the vertices of `other` so that both can be drawn with a single call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the primitive types differ or are not list types. Strips, fans and loops cannot
+    /// be concatenated without connecting them.
+    pub fn append(&mut self, other: &Geometry<V>) {
+        assert_eq!(
+            self.primitive_type, other.primitive_type,
+            "Only geometries of the same primitive type can be merged"
+        );
+        assert!(
+            is_list(self.primitive_type),
+            "Geometries of type {:?} cannot be merged",
+            self.primitive_type
+        );
+        self.vertices.extend_from_slice(&other.vertices);
+    }
+
+    /// Merges all `geometries` into a single geometry of type `primitive_type`.
+    ///
+    /// # Panics
+    ///
+    /// See `Geometry::append`.
+    pub fn merge<'a, I>(primitive_type: PrimitiveType, geometries: I) -> Geometry<V>
+    where
+        I: IntoIterator<Item = &'a Geometry<V>>,
+        V: 'a,
+    {
+        let mut merged = Geometry::empty(primitive_type);
+        for geometry in geometries {
+            merged.append(geometry);
+        }
+        merged
+    }
+
+    /// Bakes one copy of `self` per transform into a single geometry, e.g. to draw a static
+    /// arrangement of identical objects with one call.
+    ///
+    /// # Panics
+    ///
+    /// See `Geometry::transform` and `Geometry::append`.
+    pub fn instanced(&self, transforms: &[Trans4d]) -> Geometry<V> {
+        let mut instances = Geometry::empty(self.primitive_type);
+        for transform in transforms {
+            instances.append(&self.transformed(transform));
+        }
+        instances
+    }
+}
+
+impl Geometry {
+    pub fn new(primitive_type: PrimitiveType) -> Geometry {
+        Geometry::empty(primitive_type)
+    }
 
     pub fn from_line_points(coordinates: Vec<Coord3d>) -> Geometry {
         let mut geometry = Geometry::new(PrimitiveType::LineStrip);
@@ -31,25 +162,50 @@ impl Geometry {
         geometry
     }
 
-    pub fn from_manifold<M: Manifold2d<Coord = Coord3d>>(coordinates: M) -> Geometry {
-        let mut surface = Geometry::new(PrimitiveType::TrianglesList);
-
-        for x in 0..coordinates.width() - 1 {
-            for y in 0..coordinates.height() - 1 {
-                let nw = coordinates.get(x, y);
-                let sw = coordinates.get(x, y + 1);
-                let ne = coordinates.get(x + 1, y);
-                let se = coordinates.get(x + 1, y + 1);
-                let mid = (nw + sw + ne + se) / 4.0;
-
-                surface.push_triangle(mid, nw, sw);
-                surface.push_triangle(mid, sw, se);
-                surface.push_triangle(mid, se, ne);
-                surface.push_triangle(mid, ne, nw);
-            }
+    /// Returns a `LinesList` with a line between every pair of points. The lines have zero
+    /// normals, so that `vertex.glsl` draws them in full color.
+    pub fn from_lines<I: IntoIterator<Item = (Coord3d, Coord3d)>>(lines: I) -> Geometry {
+        let mut geometry = Geometry::new(PrimitiveType::LinesList);
+        for (from, to) in lines {
+            geometry
+                .vertices
+                .push(VertexAttribute::without_normal(from));
+            geometry.vertices.push(VertexAttribute::without_normal(to));
         }
+        geometry
+    }
 
-        surface
+    /// Triangulates the quads between neighboring grid lines with `QuadTriangulation::CenterFan`.
+    pub fn from_manifold<M: Manifold2d<Coord = Coord3d>>(coordinates: M) -> Geometry {
+        Geometry::from_manifold_with(coordinates, QuadTriangulation::CenterFan)
+    }
+
+    /// Like `from_manifold` but with the given triangulation. Triangles collapsed by coinciding
+    /// grid points, e.g. at the poles of `manifold::sphere`, are skipped.
+    pub fn from_manifold_with<M: Manifold2d<Coord = Coord3d>>(
+        coordinates: M,
+        triangulation: QuadTriangulation,
+    ) -> Geometry {
+        triangulate_manifold(coordinates, triangulation, |position, normal, _| {
+            VertexAttribute::new(position, normal)
+        })
+    }
+
+    /// Extracts the surface where `field` equals `iso_value` by sampling it at the given number of
+    /// `samples` along each axis of `bounds`. The normals follow the gradient of `field`. See
+    /// `isosurface::marching_cubes` for the orientation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are less than two samples along any axis.
+    pub fn from_isosurface<F: ScalarField>(
+        field: &F,
+        bounds: Aabb,
+        samples: [usize; 3],
+        iso_value: f32,
+    ) -> Geometry {
+        let grid = ScalarGrid::sample(field, bounds, samples);
+        isosurface::extract_isosurface(&grid, iso_value, field)
     }
 
     pub fn push_triangle(&mut self, v1: Coord3d, v2: Coord3d, v3: Coord3d) {
@@ -59,17 +215,563 @@ impl Geometry {
             self.vertices.push(VertexAttribute::new(vertex, normal));
         }
     }
+
+    /// Replaces the normal of every triangle corner by the weighted average of the normals of all
+    /// faces sharing the corner's position. Faces whose normals deviate by more than
+    /// `crease_angle` from the corner's own face do not contribute, so edges sharper than
+    /// `crease_angle` stay hard.
+    ///
+    /// Degenerate faces do not contribute to their neighbors but receive the average of all faces
+    /// around each of their corners.
+    ///
+    /// Every corner visits all corners at the same position, so the running time grows
+    /// quadratically with the number of faces meeting at a vertex, e.g. at the poles of
+    /// `manifold::sphere`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self` is not a `TrianglesList` or its vertex count is not a multiple of 3.
+    pub fn smooth_normals(&mut self, crease_angle: Rad<f32>, weighting: NormalWeighting) {
+        assert_eq!(
+            self.primitive_type,
+            PrimitiveType::TrianglesList,
+            "Normals can only be smoothed for triangle lists"
+        );
+        assert_eq!(
+            self.vertices.len() % 3,
+            0,
+            "Triangle list has an incomplete triangle"
+        );
+
+        let face_normals = self
+            .vertices
+            .chunks_exact(3)
+            .map(|triangle| {
+                face_normal_and_area(
+                    triangle[0].position(),
+                    triangle[1].position(),
+                    triangle[2].position(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let corner_weight = |corner: usize| {
+            let (_, area) = face_normals[corner / 3]?;
+            let weight = match weighting {
+                NormalWeighting::Angle => {
+                    let first_corner = corner - corner % 3;
+                    let position = self.vertices[corner].position();
+                    let next = self.vertices[first_corner + (corner + 1) % 3].position();
+                    let previous = self.vertices[first_corner + (corner + 2) % 3].position();
+                    (next - position).angle(previous - position).0
+                }
+                NormalWeighting::Area => area,
+            };
+            Some(weight)
+        };
+
+        let (welded_positions, position_indices) = weld_positions(
+            self.vertices.iter().map(VertexAttribute::position),
+            POSITION_TOLERANCE,
+        );
+        let mut groups = vec![Vec::new(); welded_positions.len()];
+        for (corner, &position_index) in position_indices.iter().enumerate() {
+            groups[position_index as usize].push(corner);
+        }
+
+        let min_cos = crease_angle.0.cos();
+        let mut smoothed_normals = Vec::with_capacity(self.vertices.len());
+        for group in &groups {
+            for &corner in group {
+                let own_normal = face_normals[corner / 3].map(|(normal, _)| normal);
+                let mut normal_sum = Coord3d::new(0.0, 0.0, 0.0);
+                for &other_corner in group {
+                    if let (Some((other_normal, _)), Some(weight)) =
+                        (face_normals[other_corner / 3], corner_weight(other_corner))
+                    {
+                        let is_on_same_side = own_normal
+                            .map(|own_normal| own_normal.dot(other_normal) >= min_cos)
+                            .unwrap_or(true);
+                        if is_on_same_side {
+                            normal_sum += weight * other_normal;
+                        }
+                    }
+                }
+                if normal_sum.magnitude2() > 0.0 {
+                    smoothed_normals.push((corner, normal_sum.normalize()));
+                }
+            }
+        }
+
+        for (corner, normal) in smoothed_normals {
+            let position = self.vertices[corner].position();
+            self.vertices[corner] = VertexAttribute::new(position, normal);
+        }
+    }
+}
+
+impl Geometry<TexVertexAttribute> {
+    /// Like `Geometry::from_manifold` but also emits texture coordinates derived from the grid
+    /// indices. `u` grows from 0 to 1 along the x axis of the grid, `v` falls from 1 to 0 along its
+    /// y axis. Mapped onto `manifold::sphere`, an equirectangular image uploaded in OpenGL's
+    /// bottom-up row order appears upright with north at `y = 0`.
+    pub fn from_manifold_textured<M: Manifold2d<Coord = Coord3d>>(
+        coordinates: M,
+    ) -> Geometry<TexVertexAttribute> {
+        Geometry::from_manifold_textured_with(coordinates, QuadTriangulation::CenterFan)
+    }
+
+    /// Like `from_manifold_textured` but with the given triangulation, see
+    /// `Geometry::from_manifold_with`.
+    pub fn from_manifold_textured_with<M: Manifold2d<Coord = Coord3d>>(
+        coordinates: M,
+        triangulation: QuadTriangulation,
+    ) -> Geometry<TexVertexAttribute> {
+        triangulate_manifold(coordinates, triangulation, TexVertexAttribute::new)
+    }
+
+    /// Derives per-vertex tangents for normal mapping from the texture coordinates, following the
+    /// conventions of MikkTSpace: The tangent points along increasing `u` and its `w` component is
+    /// the sign of the bitangent, which points along increasing `v`. See `TangentVertexAttribute`.
+    ///
+    /// The tangents of corners sharing all attributes are averaged, weighted by the corner angles.
+    /// Corners of faces with mirrored texture coordinates are averaged separately. The tangents are
+    /// orthogonalized against the vertex normals.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self` is not a `TrianglesList` or its vertex count is not a multiple of 3.
+    pub fn with_tangents(&self) -> Geometry<TangentVertexAttribute> {
+        assert_eq!(
+            self.primitive_type,
+            PrimitiveType::TrianglesList,
+            "Tangents can only be generated for triangle lists"
+        );
+        assert_eq!(
+            self.vertices.len() % 3,
+            0,
+            "Triangle list has an incomplete triangle"
+        );
+
+        // The normalized tangent of every face, if its texture coordinates span an area, and
+        // whether the texture coordinates keep the winding order.
+        let face_tangents = self
+            .vertices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let edge1 = triangle[1].position() - triangle[0].position();
+                let edge2 = triangle[2].position() - triangle[0].position();
+                let tex_edge1 = triangle[1].tex_coord() - triangle[0].tex_coord();
+                let tex_edge2 = triangle[2].tex_coord() - triangle[0].tex_coord();
+                let tex_area = tex_edge1.perp_dot(tex_edge2);
+                let tangent = (tex_edge2.y * edge1 - tex_edge1.y * edge2) * tex_area.signum();
+                let tangent = if tex_area != 0.0 && tangent.magnitude2() > 0.0 {
+                    Some(tangent.normalize())
+                } else {
+                    None
+                };
+                (tangent, tex_area >= 0.0)
+            })
+            .collect::<Vec<_>>();
+
+        let mut spatial_hash = SpatialHash::new(POSITION_TOLERANCE);
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut corner_groups = Vec::with_capacity(self.vertices.len());
+        for (corner, vertex) in self.vertices.iter().enumerate() {
+            let is_preserving = face_tangents[corner / 3].1;
+            let existing = spatial_hash.find(vertex.position(), |group| {
+                let representative = groups[group as usize][0];
+                face_tangents[representative / 3].1 == is_preserving
+                    && vertex.is_near(&self.vertices[representative], POSITION_TOLERANCE)
+            });
+            let group = match existing {
+                Some(group) => group,
+                None => {
+                    let group = groups.len() as u32;
+                    groups.push(Vec::new());
+                    spatial_hash.insert(vertex.position(), group);
+                    group
+                }
+            };
+            groups[group as usize].push(corner);
+            corner_groups.push(group as usize);
+        }
+
+        let group_tangents = groups
+            .iter()
+            .map(|group| {
+                let normal = self.vertices[group[0]].normal();
+                let tangent_sum = group
+                    .iter()
+                    .fold(Coord3d::new(0.0, 0.0, 0.0), |sum, &corner| {
+                        let tangent = match face_tangents[corner / 3].0 {
+                            Some(tangent) => tangent,
+                            None => return sum,
+                        };
+                        let first_corner = corner - corner % 3;
+                        let position = self.vertices[corner].position();
+                        let next = self.vertices[first_corner + (corner + 1) % 3].position();
+                        let previous = self.vertices[first_corner + (corner + 2) % 3].position();
+                        let angle = (next - position).angle(previous - position).0;
+                        let projected = tangent - normal.dot(tangent) * normal;
+                        if angle.is_finite() && projected.magnitude2() > 0.0 {
+                            sum + angle * projected.normalize()
+                        } else {
+                            sum
+                        }
+                    });
+                let tangent = tangent_sum - normal.dot(tangent_sum) * normal;
+                if tangent.magnitude2() > 0.0 && tangent.magnitude2().is_finite() {
+                    tangent.normalize()
+                } else {
+                    any_perpendicular(normal)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let vertices = self
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(corner, vertex)| {
+                let sign = if face_tangents[corner / 3].1 {
+                    1.0
+                } else {
+                    -1.0
+                };
+                TangentVertexAttribute::new(
+                    vertex.position(),
+                    vertex.normal(),
+                    vertex.tex_coord(),
+                    group_tangents[corner_groups[corner]].extend(sign),
+                )
+            })
+            .collect();
+
+        Geometry {
+            primitive_type: self.primitive_type,
+            vertices,
+        }
+    }
+}
+
+/// Returns a unit vector perpendicular to `normal`, used where texture coordinates do not define
+/// a tangent.
+fn any_perpendicular(normal: Coord3d) -> Coord3d {
+    let axis = if normal.x.abs() < 0.9 {
+        Coord3d::unit_x()
+    } else {
+        Coord3d::unit_y()
+    };
+    let perpendicular = axis - normal.dot(axis) * normal;
+    if perpendicular.magnitude2() > 0.0 {
+        perpendicular.normalize()
+    } else {
+        axis
+    }
+}
+
+fn triangulate_manifold<M, V, F>(
+    coordinates: M,
+    triangulation: QuadTriangulation,
+    vertex: F,
+) -> Geometry<V>
+where
+    M: Manifold2d<Coord = Coord3d>,
+    F: Fn(Coord3d, Coord3d, Coord2d) -> V,
+{
+    let mut surface = Geometry::empty(PrimitiveType::TrianglesList);
+
+    let max_x = (coordinates.width() - 1) as f32;
+    let max_y = (coordinates.height() - 1) as f32;
+    let grid_point = |x: usize, y: usize| {
+        let tex_coord = Coord2d::new(x as f32 / max_x, 1.0 - y as f32 / max_y);
+        (coordinates.get(x, y), tex_coord)
+    };
+
+    for x in 0..coordinates.width() - 1 {
+        for y in 0..coordinates.height() - 1 {
+            let nw = grid_point(x, y);
+            let sw = grid_point(x, y + 1);
+            let ne = grid_point(x + 1, y);
+            let se = grid_point(x + 1, y + 1);
+            let is_split_at_nw = match triangulation {
+                QuadTriangulation::FixedDiagonal => true,
+                QuadTriangulation::AlternatingDiagonal => (x + y) % 2 == 0,
+                QuadTriangulation::ShortestDiagonal => {
+                    (se.0 - nw.0).magnitude2() <= (ne.0 - sw.0).magnitude2()
+                }
+                QuadTriangulation::CenterFan => false,
+            };
+
+            // Every triangle runs through the corners in the order nw, sw, se, ne.
+            let triangles = if triangulation == QuadTriangulation::CenterFan {
+                let mid = (
+                    (nw.0 + sw.0 + ne.0 + se.0) / 4.0,
+                    (nw.1 + sw.1 + ne.1 + se.1) / 4.0,
+                );
+                vec![(mid, nw, sw), (mid, sw, se), (mid, se, ne), (mid, ne, nw)]
+            } else if is_split_at_nw {
+                vec![(nw, sw, se), (nw, se, ne)]
+            } else {
+                vec![(sw, se, ne), (sw, ne, nw)]
+            };
+
+            for &(v1, v2, v3) in &triangles {
+                let normal = match face_normal_and_area(v1.0, v2.0, v3.0) {
+                    Some((normal, _)) => normal,
+                    None => continue,
+                };
+                for &(position, tex_coord) in [v1, v2, v3].iter() {
+                    surface.vertices.push(vertex(position, normal, tex_coord));
+                }
+            }
+        }
+    }
+
+    surface
+}
+
+impl<V: Vertex> LoadOnGpu for Geometry<V> {
+    type Vertex = V;
+
+    fn load(self, facade: &Display) -> GpuObjectHandle<Self> {
+        GpuObjectHandle {
+            buffer: VertexBuffer::new(facade, &self.vertices).unwrap(),
+            index_buffer: None,
+            loaded_object: self,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct IndexedGeometry<V = VertexAttribute> {
+    pub primitive_type: PrimitiveType,
+    pub vertices: Vec<V>,
+    pub indices: Vec<u32>,
+}
+
+impl<V> IndexedGeometry<V> {
+    pub fn empty(primitive_type: PrimitiveType) -> IndexedGeometry<V> {
+        IndexedGeometry {
+            primitive_type,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+}
+
+impl IndexedGeometry {
+    pub fn new(primitive_type: PrimitiveType) -> IndexedGeometry {
+        IndexedGeometry::empty(primitive_type)
+    }
+}
+
+impl<V: MeshVertex> IndexedGeometry<V> {
+    /// Welds all vertices whose attributes differ by at most `tolerance`.
+    /// A `tolerance` of `0.0` only merges exact duplicates.
+    pub fn from_geometry(geometry: &Geometry<V>, tolerance: f32) -> IndexedGeometry<V> {
+        let mut indexed = IndexedGeometry::empty(geometry.primitive_type);
+        let mut spatial_hash = SpatialHash::new(tolerance);
+
+        for vertex in &geometry.vertices {
+            let position = vertex.position();
+            let vertices = &indexed.vertices;
+            let existing = spatial_hash.find(position, |index| {
+                vertex.is_near(&vertices[index as usize], tolerance)
+            });
+            let index = match existing {
+                Some(index) => index,
+                None => {
+                    let index = indexed.vertices.len() as u32;
+                    indexed.vertices.push(*vertex);
+                    spatial_hash.insert(position, index);
+                    index
+                }
+            };
+            indexed.indices.push(index);
+        }
+
+        indexed
+    }
+
+    /// See `Geometry::transform`.
+    pub fn transform(&mut self, transform: &Trans4d) {
+        let normal_transform = transform::transform_normals(transform);
+        for vertex in &mut self.vertices {
+            *vertex = vertex.transformed(transform, &normal_transform);
+        }
+        if self.primitive_type == PrimitiveType::TrianglesList && transform.determinant() < 0.0 {
+            for triangle in self.indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+    }
+
+    pub fn transformed(&self, transform: &Trans4d) -> IndexedGeometry<V> {
+        let mut geometry = self.clone();
+        geometry.transform(transform);
+        geometry
+    }
+
+    /// Appends the vertices and indices of `other`, offsetting its indices accordingly.
+    ///
+    /// # Panics
+    ///
+    /// See `Geometry::append`.
+    pub fn append(&mut self, other: &IndexedGeometry<V>) {
+        assert_eq!(
+            self.primitive_type, other.primitive_type,
+            "Only geometries of the same primitive type can be merged"
+        );
+        assert!(
+            is_list(self.primitive_type),
+            "Geometries of type {:?} cannot be merged",
+            self.primitive_type
+        );
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices
+            .extend(other.indices.iter().map(|&index| index + offset));
+    }
+
+    pub fn to_geometry(&self) -> Geometry<V> {
+        let mut geometry = Geometry::empty(self.primitive_type);
+        geometry.vertices.extend(
+            self.indices
+                .iter()
+                .map(|&index| self.vertices[index as usize]),
+        );
+        geometry
+    }
 }
 
-impl LoadOnGpu for Geometry {
+impl<V: Vertex> LoadOnGpu for IndexedGeometry<V> {
+    type Vertex = V;
+
     fn load(self, facade: &Display) -> GpuObjectHandle<Self> {
         GpuObjectHandle {
             buffer: VertexBuffer::new(facade, &self.vertices).unwrap(),
+            index_buffer: Some(
+                IndexBuffer::new(facade, self.primitive_type, &self.indices).unwrap(),
+            ),
             loaded_object: self,
         }
     }
 }
 
+/// Returns the unit normal and the area of a triangle or `None` if the triangle is degenerate.
+pub(crate) fn face_normal_and_area(
+    v1: Coord3d,
+    v2: Coord3d,
+    v3: Coord3d,
+) -> Option<(Coord3d, f32)> {
+    let normal = (v3 - v1).cross(v3 - v2);
+    let double_area = normal.magnitude();
+    let longest_edge2 = (v2 - v1)
+        .magnitude2()
+        .max((v3 - v2).magnitude2())
+        .max((v1 - v3).magnitude2());
+    if double_area > DEGENERACY_THRESHOLD * longest_edge2 && double_area.is_finite() {
+        Some((normal / double_area, double_area / 2.0))
+    } else {
+        None
+    }
+}
+
+fn is_list(primitive_type: PrimitiveType) -> bool {
+    matches!(
+        primitive_type,
+        PrimitiveType::Points
+            | PrimitiveType::LinesList
+            | PrimitiveType::LinesListAdjacency
+            | PrimitiveType::TrianglesList
+            | PrimitiveType::TrianglesListAdjacency
+            | PrimitiveType::Patches { .. }
+    )
+}
+
+/// Merges positions that are at most `tolerance` apart. Returns the remaining positions and, for
+/// every input position, the index of the position it was merged into.
+pub(crate) fn weld_positions<I: IntoIterator<Item = Coord3d>>(
+    positions: I,
+    tolerance: f32,
+) -> (Vec<Coord3d>, Vec<u32>) {
+    let mut welded_positions: Vec<Coord3d> = Vec::new();
+    let mut indices = Vec::new();
+    let mut spatial_hash = SpatialHash::new(tolerance);
+
+    for position in positions {
+        let existing = spatial_hash.find(position, |index| {
+            (welded_positions[index as usize] - position).magnitude2() <= tolerance * tolerance
+        });
+        let index = match existing {
+            Some(index) => index,
+            None => {
+                let index = welded_positions.len() as u32;
+                welded_positions.push(position);
+                spatial_hash.insert(position, index);
+                index
+            }
+        };
+        indices.push(index);
+    }
+
+    (welded_positions, indices)
+}
+
+/// Buckets positions into cubic cells so that all candidates within `tolerance` of a position
+/// are found by visiting the 27 surrounding cells.
+pub(crate) struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i64, i64, i64), Vec<u32>>,
+}
+
+impl SpatialHash {
+    pub(crate) fn new(tolerance: f32) -> SpatialHash {
+        SpatialHash {
+            cell_size: tolerance.max(1e-6),
+            cells: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, position: Coord3d, index: u32) {
+        self.cells
+            .entry(self.cell_of(position))
+            .or_default()
+            .push(index);
+    }
+
+    pub(crate) fn find<F: FnMut(u32) -> bool>(
+        &self,
+        position: Coord3d,
+        mut predicate: F,
+    ) -> Option<u32> {
+        let (x, y, z) = self.cell_of(position);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(indices) = self.cells.get(&(x + dx, y + dy, z + dz)) {
+                        if let Some(&index) = indices.iter().find(|&&index| predicate(index)) {
+                            return Some(index);
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn cell_of(&self, position: Coord3d) -> (i64, i64, i64) {
+        let cell = position / self.cell_size;
+        (
+            cell.x.floor() as i64,
+            cell.y.floor() as i64,
+            cell.z.floor() as i64,
+        )
+    }
+}
+
 pub fn xy_grid(divisions: (usize, usize)) -> Geometry {
     let mut geometry = Geometry::new(PrimitiveType::LinesList);
 
@@ -97,3 +799,184 @@ pub fn xy_grid(divisions: (usize, usize)) -> Geometry {
 
     geometry
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifold;
+    use crate::solids;
+
+    #[test]
+    fn smoothing_keeps_cube_edges_sharp() {
+        let cube = solids::cube();
+        let mut smoothed = cube.clone();
+        smoothed.smooth_normals(Rad(0.5), NormalWeighting::Angle);
+
+        for (smoothed, original) in smoothed.vertices.iter().zip(&cube.vertices) {
+            assert!((smoothed.normal() - original.normal()).magnitude() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn smoothing_rounds_sphere() {
+        for &weighting in [NormalWeighting::Angle, NormalWeighting::Area].iter() {
+            let mut sphere = Geometry::from_manifold_with(
+                manifold::sphere(24, 12),
+                QuadTriangulation::FixedDiagonal,
+            );
+            sphere.smooth_normals(Rad(1.0), weighting);
+
+            for vertex in &sphere.vertices {
+                let expected = vertex.position().normalize();
+                assert!(vertex.normal().dot(expected) > 0.995);
+            }
+        }
+    }
+
+    #[test]
+    fn manifold_triangulations_skip_collapsed_triangles() {
+        for &triangulation in [
+            QuadTriangulation::FixedDiagonal,
+            QuadTriangulation::AlternatingDiagonal,
+            QuadTriangulation::ShortestDiagonal,
+            QuadTriangulation::CenterFan,
+        ]
+        .iter()
+        {
+            let sphere = Geometry::from_manifold_with(manifold::sphere(8, 4), triangulation);
+            let textured =
+                Geometry::from_manifold_textured_with(manifold::sphere(8, 4), triangulation);
+            assert_eq!(sphere.vertices.len(), textured.vertices.len());
+            let triangles_per_quad = match triangulation {
+                QuadTriangulation::CenterFan => 4,
+                _ => 2,
+            };
+            // One triangle of every quad at the two poles collapses.
+            assert_eq!(
+                sphere.vertices.len() / 3,
+                8 * 4 * triangles_per_quad - 2 * 8
+            );
+
+            for triangle in sphere.vertices.chunks_exact(3) {
+                let (v1, v2, v3) = (
+                    triangle[0].position(),
+                    triangle[1].position(),
+                    triangle[2].position(),
+                );
+                assert!(face_normal_and_area(v1, v2, v3).is_some());
+                // Outward, like the fan of `from_manifold`.
+                assert!(triangle[0].normal().dot(v1 + v2 + v3) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn manifold_defaults_to_center_fan() {
+        let default = Geometry::from_manifold(manifold::torus(6, 4, 0.3));
+        let fan =
+            Geometry::from_manifold_with(manifold::torus(6, 4, 0.3), QuadTriangulation::CenterFan);
+        assert_eq!(default.vertices.len(), 6 * 4 * 4 * 3);
+        for (default, fan) in default.vertices.iter().zip(&fan.vertices) {
+            assert_eq!(default.position(), fan.position());
+        }
+        let textured = Geometry::from_manifold_textured(manifold::torus(6, 4, 0.3));
+        assert_eq!(textured.vertices.len(), default.vertices.len());
+    }
+
+    #[test]
+    #[should_panic(expected = "incomplete triangle")]
+    fn smoothing_rejects_incomplete_triangles() {
+        let mut geometry = solids::cube();
+        geometry.vertices.pop();
+        geometry.smooth_normals(Rad(1.0), NormalWeighting::Angle);
+    }
+
+    /// Two triangles covering the unit square at `x_offset` in the `z = 0` plane, with texture
+    /// coordinates computed by `tex_coord` from the positions.
+    fn textured_square<F: Fn(Coord3d) -> Coord2d>(
+        x_offset: f32,
+        tex_coord: F,
+    ) -> Vec<TexVertexAttribute> {
+        let corners = [
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (1.0, 1.0),
+            (0.0, 0.0),
+            (1.0, 1.0),
+            (0.0, 1.0),
+        ];
+        corners
+            .iter()
+            .map(|&(x, y)| {
+                let position = Coord3d::new(x + x_offset, y, 0.0);
+                TexVertexAttribute::new(position, Coord3d::unit_z(), tex_coord(position))
+            })
+            .collect()
+    }
+
+    fn textured_geometry(vertices: Vec<TexVertexAttribute>) -> Geometry<TexVertexAttribute> {
+        Geometry {
+            primitive_type: PrimitiveType::TrianglesList,
+            vertices,
+        }
+    }
+
+    #[test]
+    fn tangents_follow_increasing_u() {
+        let plane = textured_geometry(textured_square(0.0, |p| Coord2d::new(p.x, p.y)));
+        for vertex in &plane.with_tangents().vertices {
+            let tangent = vertex.tangent();
+            assert!((tangent.truncate() - Coord3d::unit_x()).magnitude() < 1e-6);
+            assert_eq!(tangent.w, 1.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_tangents_are_not_averaged() {
+        // The right square mirrors the texture of the left one along the shared edge at x = 1,
+        // where the corners agree in every attribute.
+        let mut vertices = textured_square(0.0, |p| Coord2d::new(p.x, p.y));
+        vertices.extend(textured_square(1.0, |p| Coord2d::new(2.0 - p.x, p.y)));
+        let tangents = textured_geometry(vertices).with_tangents();
+        for vertex in &tangents.vertices[..6] {
+            let tangent = vertex.tangent();
+            assert!((tangent.truncate() - Coord3d::unit_x()).magnitude() < 1e-6);
+            assert_eq!(tangent.w, 1.0);
+        }
+        for vertex in &tangents.vertices[6..] {
+            let tangent = vertex.tangent();
+            assert!((tangent.truncate() + Coord3d::unit_x()).magnitude() < 1e-6);
+            assert_eq!(tangent.w, -1.0);
+        }
+    }
+
+    #[test]
+    fn uv_seams_keep_separate_tangents() {
+        // The texture of the right square is rotated, so its tangents point along y. The corners
+        // on the shared edge at x = 1 have equal positions but different texture coordinates.
+        let mut vertices = textured_square(0.0, |p| Coord2d::new(p.x, p.y));
+        vertices.extend(textured_square(1.0, |p| Coord2d::new(p.y + 2.0, 2.0 - p.x)));
+        let tangents = textured_geometry(vertices).with_tangents();
+        for vertex in &tangents.vertices[..6] {
+            let tangent = vertex.tangent();
+            assert!((tangent.truncate() - Coord3d::unit_x()).magnitude() < 1e-6);
+            assert_eq!(tangent.w, 1.0);
+        }
+        for vertex in &tangents.vertices[6..] {
+            let tangent = vertex.tangent();
+            assert!((tangent.truncate() - Coord3d::unit_y()).magnitude() < 1e-6);
+            assert_eq!(tangent.w, 1.0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "incomplete triangle")]
+    fn tangents_reject_incomplete_triangles() {
+        let mut geometry = Geometry::from_manifold_textured_with(
+            manifold::sphere(4, 2),
+            QuadTriangulation::FixedDiagonal,
+        );
+        geometry.vertices.pop();
+        geometry.with_tangents();
+    }
+}
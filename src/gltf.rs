@@ -0,0 +1,540 @@
+use crate::geometry::Geometry;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use cgmath::SquareMatrix;
+use glium::backend::glutin::Display;
+use glium::index::PrimitiveType;
+use glium::texture::RawImage2d;
+use glium::texture::Texture2d;
+use gltf::buffer;
+use gltf::image;
+use gltf::image::Format;
+use gltf::mesh::Mode;
+use gltf::Document;
+use gltf::Gltf;
+use gltf::Node;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// The meshes, node instances and base color images of a glTF 2.0 asset.
+pub struct GltfScene {
+    /// The primitives of every glTF mesh, in the order of the document.
+    pub meshes: Vec<Vec<GltfPrimitive>>,
+    /// The world transform and mesh index of every node that references a mesh.
+    pub instances: Vec<(Trans4d, usize)>,
+    pub images: Vec<TextureImage>,
+}
+
+pub struct GltfPrimitive {
+    pub geometry: GltfGeometry,
+    pub base_color: Coord3d,
+    pub opacity: f32,
+    /// Index into `GltfScene::images`. Only set for textured geometry.
+    pub base_color_texture: Option<usize>,
+}
+
+pub enum GltfGeometry {
+    Plain(Geometry),
+    Textured(Geometry<TexVertexAttribute>),
+}
+
+/// RGBA pixel data with the first row at the top, matching glTF's texture coordinate convention.
+pub struct TextureImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum GltfError {
+    Import(gltf::Error),
+    MissingPositions {
+        mesh: usize,
+        primitive: usize,
+    },
+    /// The primitive has a base color texture but lacks the texture coordinates it refers to.
+    MissingTexCoords {
+        mesh: usize,
+        primitive: usize,
+    },
+    /// An index refers to a missing vertex or a vertex attribute has a different number of
+    /// entries than the positions.
+    InvalidPrimitive {
+        mesh: usize,
+        primitive: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GltfError::Import(err) => write!(f, "Could not import glTF asset: {}", err),
+            GltfError::MissingPositions { mesh, primitive } => write!(
+                f,
+                "Primitive {} of mesh {} has no positions",
+                primitive, mesh
+            ),
+            GltfError::MissingTexCoords { mesh, primitive } => write!(
+                f,
+                "Primitive {} of mesh {} has a texture but no texture coordinates",
+                primitive, mesh
+            ),
+            GltfError::InvalidPrimitive {
+                mesh,
+                primitive,
+                message,
+            } => write!(f, "Primitive {} of mesh {}: {}", primitive, mesh, message),
+        }
+    }
+}
+
+impl Error for GltfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GltfError::Import(err) => Some(err),
+            GltfError::MissingPositions { .. }
+            | GltfError::MissingTexCoords { .. }
+            | GltfError::InvalidPrimitive { .. } => None,
+        }
+    }
+}
+
+impl From<gltf::Error> for GltfError {
+    fn from(err: gltf::Error) -> Self {
+        GltfError::Import(err)
+    }
+}
+
+impl GltfScene {
+    /// Loads a `.gltf` or `.glb` file. External buffers and images are resolved relative to `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<GltfScene, GltfError> {
+        let (document, buffers, images) = gltf::import(path)?;
+        GltfScene::from_document(&document, &buffers, images)
+    }
+
+    /// Loads `.glb` data or `.gltf` data with embedded base64 buffers and images. External
+    /// references are resolved relative to the directory `base_path`. Without a base path, they
+    /// are rejected with `gltf::Error::ExternalReferenceInSliceImport`.
+    pub fn from_slice(data: &[u8], base_path: Option<&Path>) -> Result<GltfScene, GltfError> {
+        // `gltf::import_slice` and `gltf::import_images` without a base path reject images
+        // embedded as data URIs, so reject external references up front and import by hand.
+        let Gltf { document, blob } = Gltf::from_slice(data)?;
+        let base_path = match base_path {
+            Some(base_path) => base_path,
+            None if has_external_references(&document) => {
+                return Err(gltf::Error::ExternalReferenceInSliceImport.into());
+            }
+            None => Path::new("."),
+        };
+        let buffers = gltf::import_buffers(&document, Some(base_path), blob)?;
+        let images = gltf::import_images(&document, Some(base_path), &buffers)?;
+        GltfScene::from_document(&document, &buffers, images)
+    }
+
+    fn from_document(
+        document: &Document,
+        buffers: &[buffer::Data],
+        images: Vec<image::Data>,
+    ) -> Result<GltfScene, GltfError> {
+        let mut meshes = Vec::new();
+        for mesh in document.meshes() {
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                primitives.push(load_primitive(&mesh, &primitive, buffers)?);
+            }
+            meshes.push(primitives);
+        }
+
+        let mut instances = Vec::new();
+        let root_nodes = match document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            Some(scene) => scene.nodes().collect(),
+            None => {
+                let child_nodes = document
+                    .nodes()
+                    .flat_map(|node| node.children().map(|child| child.index()))
+                    .collect::<Vec<_>>();
+                document
+                    .nodes()
+                    .filter(|node| !child_nodes.contains(&node.index()))
+                    .collect::<Vec<_>>()
+            }
+        };
+        for node in root_nodes {
+            collect_instances(&node, Trans4d::identity(), &mut instances);
+        }
+
+        Ok(GltfScene {
+            meshes,
+            instances,
+            images: images.into_iter().map(TextureImage::from).collect(),
+        })
+    }
+}
+
+impl TextureImage {
+    pub fn to_texture(&self, facade: &Display) -> Texture2d {
+        let image = RawImage2d::from_raw_rgba(self.rgba.clone(), (self.width, self.height));
+        Texture2d::new(facade, image).unwrap()
+    }
+}
+
+impl From<image::Data> for TextureImage {
+    fn from(data: image::Data) -> Self {
+        let to_rgba = |pixel: &[u8]| match pixel.len() {
+            1 => [pixel[0], pixel[0], pixel[0], 255],
+            // Grey with alpha.
+            2 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+            3 => [pixel[0], pixel[1], pixel[2], 255],
+            _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+        };
+        let high_bytes = |pixels: &[u8]| pixels.chunks(2).map(|bytes| bytes[1]).collect::<Vec<_>>();
+        let clamped_floats = |pixels: &[u8]| {
+            pixels
+                .chunks(4)
+                .map(|bytes| {
+                    let value = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let (channels, bytes) = match data.format {
+            Format::R8 => (1, data.pixels),
+            Format::R8G8 => (2, data.pixels),
+            Format::R8G8B8 => (3, data.pixels),
+            Format::R8G8B8A8 => (4, data.pixels),
+            Format::R16 => (1, high_bytes(&data.pixels)),
+            Format::R16G16 => (2, high_bytes(&data.pixels)),
+            Format::R16G16B16 => (3, high_bytes(&data.pixels)),
+            Format::R16G16B16A16 => (4, high_bytes(&data.pixels)),
+            Format::R32G32B32FLOAT => (3, clamped_floats(&data.pixels)),
+            Format::R32G32B32A32FLOAT => (4, clamped_floats(&data.pixels)),
+        };
+
+        TextureImage {
+            width: data.width,
+            height: data.height,
+            rgba: bytes.chunks(channels).flat_map(to_rgba).collect(),
+        }
+    }
+}
+
+fn has_external_references(document: &Document) -> bool {
+    let is_external = |uri: &str| !uri.starts_with("data:");
+    let external_buffer = document.buffers().any(|buffer| match buffer.source() {
+        buffer::Source::Uri(uri) => is_external(uri),
+        buffer::Source::Bin => false,
+    });
+    let external_image = document.images().any(|image| match image.source() {
+        image::Source::Uri { uri, .. } => is_external(uri),
+        image::Source::View { .. } => false,
+    });
+    external_buffer || external_image
+}
+
+fn collect_instances(
+    node: &Node,
+    parent_transform: Trans4d,
+    instances: &mut Vec<(Trans4d, usize)>,
+) {
+    let transform = parent_transform * Trans4d::from(node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        instances.push((transform, mesh.index()));
+    }
+    for child in node.children() {
+        collect_instances(&child, transform, instances);
+    }
+}
+
+fn load_primitive(
+    mesh: &gltf::Mesh,
+    primitive: &gltf::Primitive,
+    buffers: &[buffer::Data],
+) -> Result<GltfPrimitive, GltfError> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let positions = reader
+        .read_positions()
+        .ok_or(GltfError::MissingPositions {
+            mesh: mesh.index(),
+            primitive: primitive.index(),
+        })?
+        .map(Coord3d::from)
+        .collect::<Vec<_>>();
+    let normals = reader
+        .read_normals()
+        .map(|normals| normals.map(Coord3d::from).collect::<Vec<_>>());
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().map(|index| index as usize).collect(),
+        None => (0..positions.len()).collect::<Vec<_>>(),
+    };
+
+    let number_of_vertices = positions.len();
+    let invalid = |message: String| GltfError::InvalidPrimitive {
+        mesh: mesh.index(),
+        primitive: primitive.index(),
+        message,
+    };
+    let check_count = |name: &str, count: usize| {
+        if count == number_of_vertices {
+            Ok(())
+        } else {
+            Err(invalid(format!(
+                "Attribute {} has {} values but there are {} vertices",
+                name, count, number_of_vertices
+            )))
+        }
+    };
+    if let Some(normals) = &normals {
+        check_count("NORMAL", normals.len())?;
+    }
+    if let Some(&index) = indices.iter().find(|&&index| index >= number_of_vertices) {
+        return Err(invalid(format!(
+            "Index refers to vertex {} but only {} vertices exist",
+            index, number_of_vertices
+        )));
+    }
+
+    let primitive_type = match primitive.mode() {
+        Mode::Points => PrimitiveType::Points,
+        Mode::Lines => PrimitiveType::LinesList,
+        Mode::LineLoop => PrimitiveType::LineLoop,
+        Mode::LineStrip => PrimitiveType::LineStrip,
+        Mode::Triangles => PrimitiveType::TrianglesList,
+        Mode::TriangleStrip => PrimitiveType::TriangleStrip,
+        Mode::TriangleFan => PrimitiveType::TriangleFan,
+    };
+
+    // glTF requires flat normals if none are given. This is only possible for triangle lists, all
+    // other primitives receive a zero normal which is rendered fully lit.
+    let vertex_normals = match normals {
+        Some(normals) => indices.iter().map(|&index| normals[index]).collect(),
+        None if primitive_type == PrimitiveType::TrianglesList => indices
+            .chunks(3)
+            .flat_map(|triangle| {
+                let normal = match triangle {
+                    &[i1, i2, i3] => {
+                        let (v1, v2, v3) = (positions[i1], positions[i2], positions[i3]);
+                        (v3 - v1).cross(v3 - v2).normalize()
+                    }
+                    _ => Coord3d::new(0.0, 0.0, 0.0),
+                };
+                vec![normal; triangle.len()]
+            })
+            .collect(),
+        None => vec![Coord3d::new(0.0, 0.0, 0.0); indices.len()],
+    };
+
+    let material = primitive.material();
+    let pbr = material.pbr_metallic_roughness();
+    let [red, green, blue, alpha] = pbr.base_color_factor();
+    let (geometry, base_color_texture) = match pbr.base_color_texture() {
+        Some(info) => {
+            let tex_coords = reader
+                .read_tex_coords(info.tex_coord())
+                .ok_or(GltfError::MissingTexCoords {
+                    mesh: mesh.index(),
+                    primitive: primitive.index(),
+                })?
+                .into_f32()
+                .map(Coord2d::from)
+                .collect::<Vec<_>>();
+            check_count("TEXCOORD", tex_coords.len())?;
+            let mut geometry = Geometry::empty(primitive_type);
+            for (&index, &normal) in indices.iter().zip(&vertex_normals) {
+                geometry.vertices.push(TexVertexAttribute::new(
+                    positions[index],
+                    normal,
+                    tex_coords[index],
+                ));
+            }
+            let image_index = info.texture().source().index();
+            (GltfGeometry::Textured(geometry), Some(image_index))
+        }
+        None => {
+            let mut geometry = Geometry::new(primitive_type);
+            for (&index, &normal) in indices.iter().zip(&vertex_normals) {
+                geometry
+                    .vertices
+                    .push(VertexAttribute::new(positions[index], normal));
+            }
+            (GltfGeometry::Plain(geometry), None)
+        }
+    };
+
+    Ok(GltfPrimitive {
+        geometry,
+        base_color: Coord3d::new(red, green, blue),
+        opacity: alpha,
+        base_color_texture,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: &str = "data:application/octet-stream;base64,\
+                            AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA";
+    const RED_PIXEL: &str = "data:image/png;base64,\
+                             iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR4nGP4\
+                             z8DwHwAFAAH/iZk9HQAAAABJRU5ErkJggg==";
+    /// Grey 128 with alpha 64.
+    const GREY_ALPHA_PIXEL: &str = "data:image/png;base64,\
+                                    iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR4\
+                                    nGNocAAAAUMAwV/UlQ0AAAAASUVORK5CYII=";
+    /// The positions of `TRIANGLE` followed by the indices 0, 1 and 5 as unsigned shorts.
+    const BAD_INDICES: &str = "data:application/octet-stream;base64,\
+                               AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAUAAAA=";
+    /// The positions of `TRIANGLE` followed by only two normals.
+    const TWO_NORMALS: &str = "data:application/octet-stream;base64,\
+                               AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/\
+                               AAAAAAAAAAAAAIA/";
+
+    fn triangle_asset(buffer_uri: &str, material: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "buffers": [{{ "byteLength": 36, "uri": "{}" }}],
+                "bufferViews": [{{ "buffer": 0, "byteLength": 36 }}],
+                "accessors": [{{
+                    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [1, 1, 0]
+                }}],
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}{} }}] }}],
+                "nodes": [{{ "mesh": 0, "translation": [0, 0, 2] }}],
+                "scenes": [{{ "nodes": [0] }}],
+                "images": [{{ "uri": "{}" }}],
+                "textures": [{{ "source": 0 }}],
+                "materials": [{{
+                    "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }} }}
+                }}]
+            }}"#,
+            buffer_uri, material, RED_PIXEL
+        )
+    }
+
+    /// A triangle whose buffer continues with the data of a second accessor after the positions.
+    fn asset_with_accessor(
+        buffer_uri: &str,
+        byte_length: usize,
+        accessor: &str,
+        use_accessor: &str,
+    ) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "buffers": [{{ "byteLength": {length}, "uri": "{uri}" }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": {rest} }}
+                ],
+                "accessors": [
+                    {{
+                        "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [1, 1, 0]
+                    }},
+                    {accessor}
+                ],
+                "meshes": [{{ "primitives": [{{ {use_accessor} }}] }}],
+                "nodes": [{{ "mesh": 0 }}],
+                "scenes": [{{ "nodes": [0] }}]
+            }}"#,
+            length = byte_length,
+            uri = buffer_uri,
+            rest = byte_length - 36,
+            accessor = accessor,
+            use_accessor = use_accessor,
+        )
+    }
+
+    #[test]
+    fn loads_embedded_buffers() {
+        let scene = GltfScene::from_slice(triangle_asset(TRIANGLE, "").as_bytes(), None).unwrap();
+
+        assert_eq!(scene.meshes.len(), 1);
+        assert_eq!(scene.instances.len(), 1);
+        assert_eq!(scene.instances[0].0.w.z, 2.0);
+        assert_eq!(scene.images[0].rgba, [255, 0, 0, 255]);
+        match &scene.meshes[0][0].geometry {
+            GltfGeometry::Plain(geometry) => {
+                assert_eq!(geometry.vertices.len(), 3);
+                assert_eq!(geometry.vertices[0].normal(), Coord3d::new(0.0, 0.0, 1.0));
+            }
+            GltfGeometry::Textured(_) => panic!("Expected plain geometry"),
+        }
+    }
+
+    #[test]
+    fn rejects_external_references_without_base_path() {
+        let asset = triangle_asset("triangle.bin", "");
+
+        match GltfScene::from_slice(asset.as_bytes(), None) {
+            Err(GltfError::Import(gltf::Error::ExternalReferenceInSliceImport)) => {}
+            _ => panic!("Expected an external reference error"),
+        }
+    }
+
+    #[test]
+    fn rejects_textures_without_tex_coords() {
+        let asset = triangle_asset(TRIANGLE, r#", "material": 0"#);
+
+        match GltfScene::from_slice(asset.as_bytes(), None) {
+            Err(GltfError::MissingTexCoords {
+                mesh: 0,
+                primitive: 0,
+            }) => {}
+            _ => panic!("Expected missing texture coordinates"),
+        }
+    }
+
+    #[test]
+    fn expands_grey_alpha_images() {
+        let asset = triangle_asset(TRIANGLE, "").replace(RED_PIXEL, GREY_ALPHA_PIXEL);
+        let scene = GltfScene::from_slice(asset.as_bytes(), None).unwrap();
+
+        assert_eq!(scene.images[0].rgba, [128, 128, 128, 64]);
+    }
+
+    #[test]
+    fn rejects_indices_of_missing_vertices() {
+        let asset = asset_with_accessor(
+            BAD_INDICES,
+            44,
+            r#"{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }"#,
+            r#""attributes": { "POSITION": 0 }, "indices": 1"#,
+        );
+
+        match GltfScene::from_slice(asset.as_bytes(), None) {
+            Err(GltfError::InvalidPrimitive {
+                mesh: 0,
+                primitive: 0,
+                ..
+            }) => {}
+            _ => panic!("Expected an invalid primitive"),
+        }
+    }
+
+    #[test]
+    fn rejects_attributes_of_different_length() {
+        let asset = asset_with_accessor(
+            TWO_NORMALS,
+            60,
+            r#"{ "bufferView": 1, "componentType": 5126, "count": 2, "type": "VEC3" }"#,
+            r#""attributes": { "POSITION": 0, "NORMAL": 1 }"#,
+        );
+
+        match GltfScene::from_slice(asset.as_bytes(), None) {
+            Err(GltfError::InvalidPrimitive {
+                mesh: 0,
+                primitive: 0,
+                ..
+            }) => {}
+            _ => panic!("Expected an invalid primitive"),
+        }
+    }
+}
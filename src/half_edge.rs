@@ -0,0 +1,625 @@
+use crate::geometry::weld_positions;
+use crate::geometry::Geometry;
+use crate::geometry::IndexedGeometry;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use glium::index::PrimitiveType;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum HalfEdgeError {
+    InvalidIndex {
+        face: usize,
+        index: u32,
+    },
+    /// The face has less than three vertices or visits a vertex twice.
+    DegenerateFace {
+        face: usize,
+    },
+    /// The directed edge is used by two faces, i.e. the edge is shared by more than two faces or
+    /// by two faces of opposite orientation.
+    NonManifoldEdge {
+        from: u32,
+        to: u32,
+    },
+    /// The faces around the vertex do not form a single fan.
+    NonManifoldVertex {
+        vertex: u32,
+    },
+}
+
+impl fmt::Display for HalfEdgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HalfEdgeError::InvalidIndex { face, index } => {
+                write!(f, "Face {} refers to missing vertex {}", face, index)
+            }
+            HalfEdgeError::DegenerateFace { face } => {
+                write!(f, "Face {} has less than three distinct vertices", face)
+            }
+            HalfEdgeError::NonManifoldEdge { from, to } => write!(
+                f,
+                "Edge from vertex {} to vertex {} is used by more than one face in this direction",
+                from, to
+            ),
+            HalfEdgeError::NonManifoldVertex { vertex } => write!(
+                f,
+                "The faces around vertex {} do not form a single fan",
+                vertex
+            ),
+        }
+    }
+}
+
+impl Error for HalfEdgeError {}
+
+#[derive(Copy, Clone, Debug)]
+struct HalfEdge {
+    origin: usize,
+    twin: usize,
+    next: usize,
+    prev: usize,
+    face: Option<usize>,
+}
+
+/// A polygon mesh with manifold connectivity. Every edge consists of two opposite half-edges.
+/// Half-edges on the boundary have no face and are linked to the next boundary half-edge, so
+/// that boundary loops can be traversed like faces.
+///
+/// Vertices, half-edges and faces are referred to by their index.
+#[derive(Clone)]
+pub struct HalfEdgeMesh {
+    pub positions: Vec<Coord3d>,
+    half_edges: Vec<HalfEdge>,
+    vertex_half_edges: Vec<Option<usize>>,
+    face_half_edges: Vec<usize>,
+}
+
+impl HalfEdgeMesh {
+    /// Builds a mesh from polygons given as counterclockwise vertex indices into `positions`.
+    pub fn from_polygons(
+        positions: Vec<Coord3d>,
+        faces: &[Vec<u32>],
+    ) -> Result<HalfEdgeMesh, HalfEdgeError> {
+        let mut half_edges = Vec::new();
+        let mut face_half_edges = Vec::with_capacity(faces.len());
+        let mut edge_map = HashMap::new();
+
+        for (face_index, face) in faces.iter().enumerate() {
+            if let Some(&index) = face
+                .iter()
+                .find(|&&index| index as usize >= positions.len())
+            {
+                return Err(HalfEdgeError::InvalidIndex {
+                    face: face_index,
+                    index,
+                });
+            }
+            let has_repeated_vertex = face
+                .iter()
+                .enumerate()
+                .any(|(i, vertex)| face[i + 1..].contains(vertex));
+            if face.len() < 3 || has_repeated_vertex {
+                return Err(HalfEdgeError::DegenerateFace { face: face_index });
+            }
+
+            let first = half_edges.len();
+            face_half_edges.push(first);
+            for (i, &from) in face.iter().enumerate() {
+                let to = face[(i + 1) % face.len()];
+                if edge_map.insert((from, to), first + i).is_some() {
+                    return Err(HalfEdgeError::NonManifoldEdge { from, to });
+                }
+                half_edges.push(HalfEdge {
+                    origin: from as usize,
+                    twin: usize::MAX,
+                    next: first + (i + 1) % face.len(),
+                    prev: first + (i + face.len() - 1) % face.len(),
+                    face: Some(face_index),
+                });
+            }
+        }
+
+        // Pair up interior half-edges and create boundary half-edges for the unpaired ones.
+        let number_of_face_half_edges = half_edges.len();
+        let mut boundary_half_edges = HashMap::new();
+        for half_edge in 0..number_of_face_half_edges {
+            let from = half_edges[half_edge].origin as u32;
+            let to = half_edges[half_edges[half_edge].next].origin as u32;
+            let twin = match edge_map.get(&(to, from)) {
+                Some(&twin) => twin,
+                None => {
+                    let twin = half_edges.len();
+                    half_edges.push(HalfEdge {
+                        origin: to as usize,
+                        twin: half_edge,
+                        next: usize::MAX,
+                        prev: usize::MAX,
+                        face: None,
+                    });
+                    if boundary_half_edges.insert(to, twin).is_some() {
+                        return Err(HalfEdgeError::NonManifoldVertex { vertex: to });
+                    }
+                    twin
+                }
+            };
+            half_edges[half_edge].twin = twin;
+        }
+
+        // A boundary half-edge ends where the next one starts.
+        for half_edge in number_of_face_half_edges..half_edges.len() {
+            let to = half_edges[half_edges[half_edge].twin].origin as u32;
+            let next = boundary_half_edges[&to];
+            half_edges[half_edge].next = next;
+            half_edges[next].prev = half_edge;
+        }
+
+        let mut vertex_half_edges = vec![None; positions.len()];
+        let mut number_of_outgoing = vec![0; positions.len()];
+        for (index, half_edge) in half_edges.iter().enumerate() {
+            number_of_outgoing[half_edge.origin] += 1;
+            if vertex_half_edges[half_edge.origin].is_none() || half_edge.face.is_none() {
+                vertex_half_edges[half_edge.origin] = Some(index);
+            }
+        }
+
+        let mesh = HalfEdgeMesh {
+            positions,
+            half_edges,
+            vertex_half_edges,
+            face_half_edges,
+        };
+
+        for (vertex, &number_of_outgoing) in number_of_outgoing.iter().enumerate() {
+            if mesh.vertex_half_edges(vertex).count() != number_of_outgoing {
+                return Err(HalfEdgeError::NonManifoldVertex {
+                    vertex: vertex as u32,
+                });
+            }
+        }
+
+        Ok(mesh)
+    }
+
+    /// Builds a mesh from a triangle list, merging corners that are at most `tolerance` apart.
+    /// Triangles collapsing to a line or point by this are dropped. The remaining triangles keep
+    /// their order, so face indices only match the triangle indices of `geometry` if no triangle
+    /// collapses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `geometry` is not a `TrianglesList`.
+    pub fn from_geometry<V: MeshVertex>(
+        geometry: &Geometry<V>,
+        tolerance: f32,
+    ) -> Result<HalfEdgeMesh, HalfEdgeError> {
+        assert_eq!(
+            geometry.primitive_type,
+            PrimitiveType::TrianglesList,
+            "Only triangle lists can be converted to a half-edge mesh"
+        );
+        let (positions, indices) = weld_positions(
+            geometry.vertices.iter().map(MeshVertex::position),
+            tolerance,
+        );
+        HalfEdgeMesh::from_triangles(positions, indices.chunks_exact(3))
+    }
+
+    /// Builds a mesh from an indexed triangle list. Vertices which only differ in attributes other
+    /// than their position, e.g. along hard edges, are merged if they are at most `tolerance`
+    /// apart. Triangles collapsing to a line or point by this are dropped, which shifts the face
+    /// indices of all following triangles like in `from_geometry`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `geometry` is not a `TrianglesList` or refers to missing vertices.
+    pub fn from_indexed<V: MeshVertex>(
+        geometry: &IndexedGeometry<V>,
+        tolerance: f32,
+    ) -> Result<HalfEdgeMesh, HalfEdgeError> {
+        assert_eq!(
+            geometry.primitive_type,
+            PrimitiveType::TrianglesList,
+            "Only triangle lists can be converted to a half-edge mesh"
+        );
+        let (positions, position_indices) = weld_positions(
+            geometry.vertices.iter().map(MeshVertex::position),
+            tolerance,
+        );
+        let triangles = geometry.indices.chunks_exact(3).map(|triangle| {
+            [
+                position_indices[triangle[0] as usize],
+                position_indices[triangle[1] as usize],
+                position_indices[triangle[2] as usize],
+            ]
+        });
+        HalfEdgeMesh::from_triangles(positions, triangles)
+    }
+
+    fn from_triangles<I, T>(
+        positions: Vec<Coord3d>,
+        triangles: I,
+    ) -> Result<HalfEdgeMesh, HalfEdgeError>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u32]>,
+    {
+        let faces = triangles
+            .into_iter()
+            .map(|triangle| triangle.as_ref().to_vec())
+            .filter(|triangle| {
+                triangle[0] != triangle[1]
+                    && triangle[1] != triangle[2]
+                    && triangle[2] != triangle[0]
+            })
+            .collect::<Vec<_>>();
+        HalfEdgeMesh::from_polygons(positions, &faces)
+    }
+
+    /// Returns a flat shaded triangle list. Polygons are triangulated as fans.
+    pub fn to_geometry(&self) -> Geometry {
+        let mut geometry = Geometry::new(PrimitiveType::TrianglesList);
+        for [v1, v2, v3] in self.triangles() {
+            geometry.push_triangle(self.positions[v1], self.positions[v2], self.positions[v3]);
+        }
+        geometry
+    }
+
+    /// Returns an indexed triangle list sharing every vertex between its faces. The normals are
+    /// the area weighted averages of the adjacent face normals. Polygons are triangulated as fans.
+    pub fn to_indexed(&self) -> IndexedGeometry {
+        let mut indexed = IndexedGeometry::new(PrimitiveType::TrianglesList);
+        indexed
+            .vertices
+            .extend((0..self.number_of_vertices()).map(|vertex| {
+                VertexAttribute::new(self.positions[vertex], self.vertex_normal(vertex))
+            }));
+        for triangle in self.triangles() {
+            indexed
+                .indices
+                .extend(triangle.iter().map(|&vertex| vertex as u32));
+        }
+        indexed
+    }
+
+    pub fn number_of_vertices(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn number_of_half_edges(&self) -> usize {
+        self.half_edges.len()
+    }
+
+    pub fn number_of_faces(&self) -> usize {
+        self.face_half_edges.len()
+    }
+
+    /// The vertex `half_edge` starts at.
+    pub fn origin(&self, half_edge: usize) -> usize {
+        self.half_edges[half_edge].origin
+    }
+
+    /// The vertex `half_edge` points to.
+    pub fn target(&self, half_edge: usize) -> usize {
+        self.origin(self.twin(half_edge))
+    }
+
+    pub fn twin(&self, half_edge: usize) -> usize {
+        self.half_edges[half_edge].twin
+    }
+
+    pub fn next(&self, half_edge: usize) -> usize {
+        self.half_edges[half_edge].next
+    }
+
+    pub fn prev(&self, half_edge: usize) -> usize {
+        self.half_edges[half_edge].prev
+    }
+
+    /// The face to the left of `half_edge` or `None` for boundary half-edges.
+    pub fn face(&self, half_edge: usize) -> Option<usize> {
+        self.half_edges[half_edge].face
+    }
+
+    pub fn is_boundary_half_edge(&self, half_edge: usize) -> bool {
+        self.face(half_edge).is_none()
+    }
+
+    /// Returns whether the vertex lies on the boundary or is isolated.
+    pub fn is_boundary_vertex(&self, vertex: usize) -> bool {
+        match self.vertex_half_edges[vertex] {
+            Some(half_edge) => self.is_boundary_half_edge(half_edge),
+            None => true,
+        }
+    }
+
+    /// Returns whether the mesh has no boundary.
+    pub fn is_closed(&self) -> bool {
+        self.half_edges
+            .iter()
+            .all(|half_edge| half_edge.face.is_some())
+    }
+
+    /// Iterates over the half-edges leaving `vertex`. For boundary vertices, the iteration starts
+    /// at the outgoing boundary half-edge.
+    pub fn vertex_half_edges(&self, vertex: usize) -> Circulator<'_> {
+        Circulator::new(self, self.vertex_half_edges[vertex], |mesh, half_edge| {
+            mesh.next(mesh.twin(half_edge))
+        })
+    }
+
+    /// Iterates over the one-ring of `vertex`, i.e. all vertices connected to it by an edge.
+    pub fn vertex_neighbors(&self, vertex: usize) -> impl Iterator<Item = usize> + '_ {
+        self.vertex_half_edges(vertex)
+            .map(move |half_edge| self.target(half_edge))
+    }
+
+    pub fn vertex_faces(&self, vertex: usize) -> impl Iterator<Item = usize> + '_ {
+        self.vertex_half_edges(vertex)
+            .filter_map(move |half_edge| self.face(half_edge))
+    }
+
+    /// Iterates over the half-edges of `face` in counterclockwise order.
+    pub fn face_half_edges(&self, face: usize) -> Circulator<'_> {
+        Circulator::new(self, Some(self.face_half_edges[face]), HalfEdgeMesh::next)
+    }
+
+    pub fn face_vertices(&self, face: usize) -> impl Iterator<Item = usize> + '_ {
+        self.face_half_edges(face)
+            .map(move |half_edge| self.origin(half_edge))
+    }
+
+    /// Iterates over the faces sharing an edge with `face`.
+    pub fn face_neighbors(&self, face: usize) -> impl Iterator<Item = usize> + '_ {
+        self.face_half_edges(face)
+            .filter_map(move |half_edge| self.face(self.twin(half_edge)))
+    }
+
+    pub fn boundary_half_edges(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.number_of_half_edges()).filter(move |&half_edge| self.face(half_edge).is_none())
+    }
+
+    /// Returns the boundary half-edges grouped into closed loops. The boundary half-edges run
+    /// clockwise around the holes when seen from the front side of the faces.
+    pub fn boundary_loops(&self) -> Vec<Vec<usize>> {
+        let mut visited = vec![false; self.number_of_half_edges()];
+        let mut loops = Vec::new();
+        for start in self.boundary_half_edges() {
+            if !visited[start] {
+                let boundary_loop =
+                    Circulator::new(self, Some(start), HalfEdgeMesh::next).collect::<Vec<_>>();
+                for &half_edge in &boundary_loop {
+                    visited[half_edge] = true;
+                }
+                loops.push(boundary_loop);
+            }
+        }
+        loops
+    }
+
+    /// Computes the normal of `face` with Newell's method, which also works for non-planar
+    /// polygons. Degenerate faces have a zero normal.
+    pub fn face_normal(&self, face: usize) -> Coord3d {
+        let normal = self.face_area_vector(face);
+        if normal.magnitude2() > 0.0 {
+            normal.normalize()
+        } else {
+            normal
+        }
+    }
+
+    /// The area weighted average of the normals of the faces around `vertex`.
+    pub fn vertex_normal(&self, vertex: usize) -> Coord3d {
+        let normal = self
+            .vertex_faces(vertex)
+            .map(|face| self.face_area_vector(face))
+            .fold(Coord3d::new(0.0, 0.0, 0.0), |sum, normal| sum + normal);
+        if normal.magnitude2() > 0.0 {
+            normal.normalize()
+        } else {
+            normal
+        }
+    }
+
+    /// Returns a vector normal to `face` whose length is the face's area.
+    fn face_area_vector(&self, face: usize) -> Coord3d {
+        let vertices = self.face_vertices(face).collect::<Vec<_>>();
+        let mut normal = Coord3d::new(0.0, 0.0, 0.0);
+        for (i, &vertex) in vertices.iter().enumerate() {
+            let current = self.positions[vertex];
+            let next = self.positions[vertices[(i + 1) % vertices.len()]];
+            normal += current.cross(next);
+        }
+        normal / 2.0
+    }
+
+    fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        (0..self.number_of_faces()).flat_map(move |face| {
+            let vertices = self.face_vertices(face).collect::<Vec<_>>();
+            (1..vertices.len() - 1).map(move |i| [vertices[0], vertices[i], vertices[i + 1]])
+        })
+    }
+}
+
+/// Iterates over a cycle of half-edges, e.g. around a face or around a vertex.
+pub struct Circulator<'a> {
+    mesh: &'a HalfEdgeMesh,
+    start: Option<usize>,
+    current: Option<usize>,
+    step: fn(&HalfEdgeMesh, usize) -> usize,
+}
+
+impl<'a> Circulator<'a> {
+    fn new(
+        mesh: &'a HalfEdgeMesh,
+        start: Option<usize>,
+        step: fn(&HalfEdgeMesh, usize) -> usize,
+    ) -> Circulator<'a> {
+        Circulator {
+            mesh,
+            start,
+            current: start,
+            step,
+        }
+    }
+}
+
+impl<'a> Iterator for Circulator<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let current = self.current?;
+        let next = (self.step)(self.mesh, current);
+        self.current = if Some(next) == self.start {
+            None
+        } else {
+            Some(next)
+        };
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solids;
+
+    fn grid_positions(width: usize, height: usize) -> Vec<Coord3d> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| Coord3d::new(x as f32, y as f32, 0.0)))
+            .collect()
+    }
+
+    #[test]
+    fn open_strip_has_one_boundary_loop() {
+        // Three quads in a row:
+        // 4 5 6 7
+        // 0 1 2 3
+        let faces = [vec![0, 1, 5, 4], vec![1, 2, 6, 5], vec![2, 3, 7, 6]];
+        let mesh = HalfEdgeMesh::from_polygons(grid_positions(4, 2), &faces).unwrap();
+
+        assert!(!mesh.is_closed());
+        assert_eq!(mesh.number_of_half_edges(), 2 * 10);
+        let loops = mesh.boundary_loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 8);
+        for &half_edge in &loops[0] {
+            assert_eq!(mesh.origin(mesh.next(half_edge)), mesh.target(half_edge));
+        }
+        // The boundary runs clockwise, i.e. opposite to the faces.
+        let first = loops[0]
+            .iter()
+            .find(|&&half_edge| mesh.origin(half_edge) == 0)
+            .unwrap();
+        assert_eq!(mesh.target(*first), 4);
+
+        assert!(mesh.is_boundary_vertex(1));
+        assert_eq!(mesh.vertex_faces(1).count(), 2);
+        let mut neighbors = mesh.vertex_neighbors(1).collect::<Vec<_>>();
+        neighbors.sort();
+        assert_eq!(neighbors, [0, 2, 5]);
+        assert_eq!(mesh.face_neighbors(1).count(), 2);
+    }
+
+    #[test]
+    fn cube_is_closed() {
+        let mesh = HalfEdgeMesh::from_geometry(&solids::cube(), 1e-5).unwrap();
+
+        assert!(mesh.is_closed());
+        assert!(mesh.boundary_loops().is_empty());
+        assert_eq!(mesh.number_of_vertices(), 8);
+        assert_eq!(mesh.number_of_faces(), 12);
+        assert_eq!(mesh.number_of_half_edges(), 36);
+        for vertex in 0..mesh.number_of_vertices() {
+            let normal = mesh.vertex_normal(vertex);
+            assert!(normal.dot(mesh.positions[vertex].normalize()) > 0.5);
+        }
+    }
+
+    #[test]
+    fn collapsed_triangles_are_dropped() {
+        let mut cube = solids::cube();
+        // A triangle whose first two corners are welded into one vertex.
+        let (first, second) = (cube.vertices[0], cube.vertices[1]);
+        let nearby = VertexAttribute::new(first.position() * (1.0 + 1e-7), first.normal());
+        cube.vertices.splice(0..0, vec![first, nearby, second]);
+        let mesh = HalfEdgeMesh::from_geometry(&cube, 1e-5).unwrap();
+
+        assert!(mesh.is_closed());
+        assert_eq!(mesh.number_of_vertices(), 8);
+        assert_eq!(mesh.number_of_faces(), 12);
+        // The faces after the collapsed triangle move up by one.
+        let face_positions = mesh
+            .face_vertices(0)
+            .map(|vertex| mesh.positions[vertex])
+            .collect::<Vec<_>>();
+        assert_eq!(face_positions.len(), 3);
+        for vertex in &cube.vertices[3..6] {
+            assert!(face_positions.contains(&vertex.position()));
+        }
+    }
+
+    #[test]
+    fn bowtie_vertex_is_rejected() {
+        // Two triangles touching at vertex 0 only.
+        let positions = vec![
+            Coord3d::new(0.0, 0.0, 0.0),
+            Coord3d::new(1.0, -1.0, 0.0),
+            Coord3d::new(1.0, 1.0, 0.0),
+            Coord3d::new(-1.0, 1.0, 0.0),
+            Coord3d::new(-1.0, -1.0, 0.0),
+        ];
+        let faces = [vec![0, 1, 2], vec![0, 3, 4]];
+
+        match HalfEdgeMesh::from_polygons(positions, &faces) {
+            Err(HalfEdgeError::NonManifoldVertex { vertex: 0 }) => {}
+            _ => panic!("Expected a non-manifold vertex"),
+        }
+    }
+
+    #[test]
+    fn edge_of_three_faces_is_rejected() {
+        let positions = vec![
+            Coord3d::new(0.0, 0.0, 0.0),
+            Coord3d::new(1.0, 0.0, 0.0),
+            Coord3d::new(0.5, 1.0, 0.0),
+            Coord3d::new(0.5, -1.0, 0.0),
+            Coord3d::new(0.5, 0.0, 1.0),
+        ];
+        let faces = [vec![0, 1, 2], vec![1, 0, 3], vec![1, 0, 4]];
+
+        match HalfEdgeMesh::from_polygons(positions, &faces) {
+            Err(HalfEdgeError::NonManifoldEdge { from: 1, to: 0 }) => {}
+            _ => panic!("Expected a non-manifold edge"),
+        }
+    }
+
+    #[test]
+    fn flipped_face_is_rejected() {
+        // 2 3
+        // 0 1
+        let faces = [vec![0, 1, 3], vec![0, 3, 2]];
+        assert!(HalfEdgeMesh::from_polygons(grid_positions(2, 2), &faces).is_ok());
+
+        let flipped = [vec![0, 1, 3], vec![0, 2, 3]];
+        match HalfEdgeMesh::from_polygons(grid_positions(2, 2), &flipped) {
+            Err(HalfEdgeError::NonManifoldEdge { from: 0, to: 3 })
+            | Err(HalfEdgeError::NonManifoldEdge { from: 3, to: 0 }) => {}
+            _ => panic!("Expected a non-manifold edge"),
+        }
+    }
+
+    #[test]
+    fn invalid_and_degenerate_faces_are_rejected() {
+        match HalfEdgeMesh::from_polygons(grid_positions(2, 2), &[vec![0, 1, 4]]) {
+            Err(HalfEdgeError::InvalidIndex { face: 0, index: 4 }) => {}
+            _ => panic!("Expected an invalid index"),
+        }
+        match HalfEdgeMesh::from_polygons(grid_positions(2, 2), &[vec![0, 1, 2], vec![0, 1, 1]]) {
+            Err(HalfEdgeError::DegenerateFace { face: 1 }) => {}
+            _ => panic!("Expected a degenerate face"),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::geometry::face_normal_and_area;
+use crate::geometry::Geometry;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use glium::index::PrimitiveType;
+
+/// Returns a segment of the given `length` along the normal of every vertex. Vertices without a
+/// normal are skipped. Like all segments of this module, the lines have zero normals, see
+/// `Geometry::from_lines`.
+pub fn vertex_normals<V: MeshVertex>(geometry: &Geometry<V>, length: f32) -> Geometry {
+    segments(
+        geometry
+            .vertices
+            .iter()
+            .map(|vertex| (vertex.position(), vertex.normal())),
+        length,
+    )
+}
+
+/// Returns a segment of the given `length` from the center of every triangle along the normal
+/// implied by its winding order. Degenerate triangles are skipped.
+///
+/// # Panics
+///
+/// Panics if `geometry` is not a `TrianglesList`.
+pub fn face_normals<V: MeshVertex>(geometry: &Geometry<V>, length: f32) -> Geometry {
+    assert_eq!(
+        geometry.primitive_type,
+        PrimitiveType::TrianglesList,
+        "Face normals require a triangle list"
+    );
+    segments(
+        geometry.vertices.chunks_exact(3).filter_map(|triangle| {
+            let (v1, v2, v3) = (
+                triangle[0].position(),
+                triangle[1].position(),
+                triangle[2].position(),
+            );
+            face_normal_and_area(v1, v2, v3).map(|(normal, _)| ((v1 + v2 + v3) / 3.0, normal))
+        }),
+        length,
+    )
+}
+
+/// Returns a segment of the given `length` along the tangent of every vertex.
+pub fn tangents(geometry: &Geometry<TangentVertexAttribute>, length: f32) -> Geometry {
+    segments(
+        geometry
+            .vertices
+            .iter()
+            .map(|vertex| (vertex.position(), vertex.tangent().truncate())),
+        length,
+    )
+}
+
+/// Returns a segment of the given `length` along the bitangent of every vertex, i.e. the cross
+/// product of normal and tangent multiplied by the bitangent sign.
+pub fn bitangents(geometry: &Geometry<TangentVertexAttribute>, length: f32) -> Geometry {
+    segments(
+        geometry.vertices.iter().map(|vertex| {
+            let tangent = vertex.tangent();
+            let bitangent = tangent.w * vertex.normal().cross(tangent.truncate());
+            (vertex.position(), bitangent)
+        }),
+        length,
+    )
+}
+
+fn segments<I: IntoIterator<Item = (Coord3d, Coord3d)>>(rays: I, length: f32) -> Geometry {
+    Geometry::from_lines(
+        rays.into_iter()
+            .filter(|(_, direction)| {
+                direction.magnitude2() > 0.0 && direction.magnitude2().is_finite()
+            })
+            .map(|(origin, direction)| (origin, origin + length * direction.normalize())),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solids;
+
+    /// Returns the segments as pairs of origin and tip.
+    fn segment_list(lines: &Geometry) -> Vec<(Coord3d, Coord3d)> {
+        assert_eq!(lines.primitive_type, PrimitiveType::LinesList);
+        lines
+            .vertices
+            .chunks_exact(2)
+            .map(|pair| {
+                assert_eq!(pair[0].normal(), Coord3d::new(0.0, 0.0, 0.0));
+                assert_eq!(pair[1].normal(), Coord3d::new(0.0, 0.0, 0.0));
+                (pair[0].position(), pair[1].position())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vertex_normals_have_the_given_length() {
+        let cube = solids::cube();
+        let segments = segment_list(&vertex_normals(&cube, 0.5));
+        assert_eq!(segments.len(), cube.vertices.len());
+        for ((origin, tip), vertex) in segments.into_iter().zip(&cube.vertices) {
+            assert_eq!(origin, vertex.position());
+            assert!((tip - origin - 0.5 * vertex.normal()).magnitude() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn vertices_without_normals_are_skipped() {
+        let lines = Geometry::from_lines(vec![(
+            Coord3d::new(0.0, 0.0, 0.0),
+            Coord3d::new(1.0, 0.0, 0.0),
+        )]);
+        assert!(vertex_normals(&lines, 1.0).vertices.is_empty());
+    }
+
+    #[test]
+    fn face_normals_start_at_the_centers() {
+        let mut geometry = solids::tetrahedron();
+        // A degenerate triangle has no normal.
+        let point = Coord3d::new(0.0, 0.0, 0.0);
+        geometry
+            .vertices
+            .extend(vec![VertexAttribute::new(point, point); 3]);
+
+        let segments = segment_list(&face_normals(&geometry, 2.0));
+        assert_eq!(segments.len(), 4);
+        for (origin, tip) in segments {
+            assert!(((tip - origin).magnitude() - 2.0).abs() < 1e-6);
+            // The faces of the tetrahedron point away from its center.
+            assert!((tip - origin).dot(origin) > 0.0);
+        }
+    }
+
+    #[test]
+    fn tangent_frames_are_orthogonal() {
+        let corners = [
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (1.0, 1.0),
+            (0.0, 0.0),
+            (1.0, 1.0),
+            (0.0, 1.0),
+        ];
+        let plane = Geometry {
+            primitive_type: PrimitiveType::TrianglesList,
+            vertices: corners
+                .iter()
+                .map(|&(x, y)| {
+                    TexVertexAttribute::new(
+                        Coord3d::new(x, y, 0.0),
+                        Coord3d::unit_z(),
+                        Coord2d::new(x, y),
+                    )
+                })
+                .collect(),
+        }
+        .with_tangents();
+        let tangents = segment_list(&tangents(&plane, 1.0));
+        let bitangents = segment_list(&bitangents(&plane, 1.0));
+        assert_eq!(tangents.len(), plane.vertices.len());
+        assert_eq!(bitangents.len(), plane.vertices.len());
+        for ((origin, tangent), (_, bitangent)) in tangents.into_iter().zip(bitangents) {
+            let (tangent, bitangent) = (tangent - origin, bitangent - origin);
+            assert!((tangent.magnitude() - 1.0).abs() < 1e-5);
+            assert!((bitangent.magnitude() - 1.0).abs() < 1e-5);
+            assert!(tangent.dot(bitangent).abs() < 1e-5);
+            assert!(tangent.z.abs() < 1e-5 && bitangent.z.abs() < 1e-5);
+        }
+    }
+}
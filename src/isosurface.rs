@@ -0,0 +1,436 @@
+use crate::bounds::Aabb;
+use crate::geometry::Geometry;
+use crate::prelude::*;
+use cgmath::ElementWise;
+use cgmath::InnerSpace;
+use glium::index::PrimitiveType;
+use std::collections::BTreeMap;
+use std::ops::Add;
+use std::ops::Mul;
+
+/// Step of the central differences in `ScalarField::gradient`.
+const GRADIENT_STEP: f32 = 1e-3;
+
+/// The corners of every cube face, counterclockwise when seen from outside. Corner `i` is offset
+/// by `i & 1` along x, `(i >> 1) & 1` along y and `(i >> 2) & 1` along z.
+const CUBE_FACES: [[usize; 4]; 6] = [
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+];
+
+/// A function `f(x, y, z)` whose level sets define implicit surfaces. Closures implement this
+/// trait with a numerically estimated gradient.
+pub trait ScalarField {
+    fn value(&self, position: Coord3d) -> f32;
+
+    fn gradient(&self, position: Coord3d) -> Coord3d {
+        let difference = |offset: Coord3d| {
+            (self.value(position + offset) - self.value(position - offset)) / (2.0 * GRADIENT_STEP)
+        };
+        Coord3d::new(
+            difference(Coord3d::new(GRADIENT_STEP, 0.0, 0.0)),
+            difference(Coord3d::new(0.0, GRADIENT_STEP, 0.0)),
+            difference(Coord3d::new(0.0, 0.0, GRADIENT_STEP)),
+        )
+    }
+}
+
+impl<F: Fn(Coord3d) -> f32> ScalarField for F {
+    fn value(&self, position: Coord3d) -> f32 {
+        self(position)
+    }
+}
+
+/// Samples of a scalar field on a regular grid spanning `bounds`. Between the samples, the grid
+/// is a scalar field itself by trilinear interpolation.
+#[derive(Clone, Debug)]
+pub struct ScalarGrid {
+    bounds: Aabb,
+    /// At least two along every axis.
+    samples: [usize; 3],
+    values: Vec<f32>,
+}
+
+impl ScalarGrid {
+    /// # Panics
+    ///
+    /// Panics if there are less than two samples along any axis.
+    pub fn sample<F: ScalarField>(field: &F, bounds: Aabb, samples: [usize; 3]) -> ScalarGrid {
+        assert!(
+            samples.iter().all(|&count| count >= 2),
+            "A scalar grid needs at least two samples along every axis"
+        );
+        let mut grid = ScalarGrid {
+            bounds,
+            samples,
+            values: Vec::with_capacity(samples[0] * samples[1] * samples[2]),
+        };
+        for z in 0..samples[2] {
+            for y in 0..samples[1] {
+                for x in 0..samples[0] {
+                    let value = field.value(grid.position([x, y, z]));
+                    grid.values.push(value);
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /// The number of samples along the x, y and z axes.
+    pub fn samples(&self) -> [usize; 3] {
+        self.samples
+    }
+
+    /// The samples in x-major order, i.e. x varies fastest.
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    pub fn at(&self, index: [usize; 3]) -> f32 {
+        self.values[self.linear_index(index)]
+    }
+
+    pub fn position(&self, index: [usize; 3]) -> Coord3d {
+        let [x, y, z] = index;
+        self.bounds.min
+            + Coord3d::new(x as f32, y as f32, z as f32).mul_element_wise(self.spacing())
+    }
+
+    /// The distance of neighboring samples along each axis.
+    pub fn spacing(&self) -> Coord3d {
+        let [x, y, z] = self.samples;
+        self.bounds.size().div_element_wise(Coord3d::new(
+            (x - 1) as f32,
+            (y - 1) as f32,
+            (z - 1) as f32,
+        ))
+    }
+
+    fn linear_index(&self, index: [usize; 3]) -> usize {
+        let [x, y, z] = index;
+        x + self.samples[0] * (y + self.samples[1] * z)
+    }
+
+    /// Estimates the gradient at a sample by central differences, or one-sided differences at the
+    /// border of the grid.
+    fn sample_gradient(&self, index: [usize; 3]) -> Coord3d {
+        let spacing = self.spacing();
+        let mut gradient = Coord3d::new(0.0, 0.0, 0.0);
+        for axis in 0..3 {
+            let mut lower = index;
+            let mut upper = index;
+            lower[axis] = index[axis].saturating_sub(1);
+            upper[axis] = (index[axis] + 1).min(self.samples[axis] - 1);
+            gradient[axis] = (self.at(upper) - self.at(lower))
+                / ((upper[axis] - lower[axis]) as f32 * spacing[axis]);
+        }
+        gradient
+    }
+
+    /// Interpolates trilinearly between the quantities `at` the eight samples around `position`.
+    /// Positions outside of the grid are clamped to its bounds.
+    fn interpolate<T, F>(&self, position: Coord3d, at: F) -> T
+    where
+        T: Add<Output = T> + Mul<f32, Output = T>,
+        F: Fn([usize; 3]) -> T,
+    {
+        let relative = (position - self.bounds.min).div_element_wise(self.spacing());
+        let mut cell = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let max_cell = self.samples[axis] - 2;
+            let coordinate = relative[axis].max(0.0).min((max_cell + 1) as f32);
+            cell[axis] = (coordinate.floor() as usize).min(max_cell);
+            fraction[axis] = coordinate - cell[axis] as f32;
+        }
+
+        let corner = |corner: usize| {
+            let index = [
+                cell[0] + (corner & 1),
+                cell[1] + ((corner >> 1) & 1),
+                cell[2] + ((corner >> 2) & 1),
+            ];
+            let weight = (0..3)
+                .map(|axis| {
+                    if (corner >> axis) & 1 == 1 {
+                        fraction[axis]
+                    } else {
+                        1.0 - fraction[axis]
+                    }
+                })
+                .product::<f32>();
+            at(index) * weight
+        };
+        (1..8).fold(corner(0), |sum, index| sum + corner(index))
+    }
+}
+
+impl ScalarField for ScalarGrid {
+    fn value(&self, position: Coord3d) -> f32 {
+        self.interpolate(position, |index| self.at(index))
+    }
+
+    /// Interpolates the gradients estimated at the samples, which is smoother than the gradient of
+    /// the trilinear interpolation.
+    fn gradient(&self, position: Coord3d) -> Coord3d {
+        self.interpolate(position, |index| self.sample_gradient(index))
+    }
+}
+
+/// Extracts the surface where `grid` equals `iso_value` as a triangle list. Values below
+/// `iso_value` count as inside, so the triangles face toward larger values, as they do for signed
+/// distance fields. Negate fields like metaballs that grow toward the inside.
+///
+/// The vertices are linearly interpolated along the grid edges and their normals follow the
+/// gradient of the grid. Instead of using the classic case table, the polygon in every cell is
+/// traced along the cell faces. Ambiguous faces are resolved by the asymptotic decider, so that
+/// the surface has no cracks between cells.
+pub fn marching_cubes(grid: &ScalarGrid, iso_value: f32) -> Geometry {
+    extract_isosurface(grid, iso_value, grid)
+}
+
+/// Like `marching_cubes` but derives the normals from the gradient of `gradient_field`, usually
+/// the field `grid` was sampled from.
+pub(crate) fn extract_isosurface<F: ScalarField>(
+    grid: &ScalarGrid,
+    iso_value: f32,
+    gradient_field: &F,
+) -> Geometry {
+    let mut surface = Geometry::new(PrimitiveType::TrianglesList);
+    let [size_x, size_y, size_z] = grid.samples;
+    for z in 0..size_z - 1 {
+        for y in 0..size_y - 1 {
+            for x in 0..size_x - 1 {
+                let corners = (0..8)
+                    .map(|corner| [x + (corner & 1), y + ((corner >> 1) & 1), z + (corner >> 2)])
+                    .collect::<Vec<_>>();
+                let values = corners
+                    .iter()
+                    .map(|&index| grid.at(index))
+                    .collect::<Vec<_>>();
+                for polygon in cell_polygons(&values, iso_value) {
+                    let points = polygon
+                        .iter()
+                        .map(|&(a, b)| {
+                            // Interpolate from the lower corner for identical results in all cells.
+                            let t = (iso_value - values[a]) / (values[b] - values[a]);
+                            let (from, to) = (grid.position(corners[a]), grid.position(corners[b]));
+                            from + t * (to - from)
+                        })
+                        .collect::<Vec<_>>();
+                    push_polygon(&mut surface, &polygon, &points, gradient_field);
+                }
+            }
+        }
+    }
+    surface
+}
+
+/// Returns the counterclockwise polygons of a cell as lists of the crossed cell edges, each given
+/// by its lower and upper corner.
+fn cell_polygons(values: &[f32], iso_value: f32) -> Vec<Vec<(usize, usize)>> {
+    let is_inside = |corner: usize| values[corner] < iso_value;
+    let edge = |a: usize, b: usize| (a.min(b), a.max(b));
+
+    // Every crossed edge is entered by one segment and left by one segment.
+    let mut successors = BTreeMap::new();
+    for face in &CUBE_FACES {
+        // Walking around the face, the surface is entered where an inside corner follows an
+        // outside corner and left where an outside corner follows an inside corner.
+        let crossings = (0..4)
+            .filter(|&index| is_inside(face[index]) != is_inside(face[(index + 1) % 4]))
+            .map(|index| {
+                let (a, b) = (face[index], face[(index + 1) % 4]);
+                (edge(a, b), is_inside(b))
+            })
+            .collect::<Vec<_>>();
+
+        // With four crossings, either the inside corners are connected across the face center or
+        // the outside corners are. The bilinear interpolant at its saddle point decides.
+        let inside_is_connected = crossings.len() == 4 && {
+            let [a, b, c, d] = [
+                values[face[0]],
+                values[face[1]],
+                values[face[2]],
+                values[face[3]],
+            ];
+            let denominator = a + c - b - d;
+            denominator != 0.0 && (a * c - b * d) / denominator < iso_value
+        };
+
+        for (index, &(crossed_edge, is_entry)) in crossings.iter().enumerate() {
+            if is_entry {
+                // Separated inside corners are cut off by connecting to the following exit.
+                let partner = if inside_is_connected {
+                    (index + crossings.len() - 1) % crossings.len()
+                } else {
+                    (index + 1) % crossings.len()
+                };
+                successors.insert(crossed_edge, crossings[partner].0);
+            }
+        }
+    }
+
+    let mut polygons = Vec::new();
+    while let Some(&start) = successors.keys().next() {
+        let mut polygon = vec![start];
+        let mut current = successors.remove(&start).unwrap();
+        while current != start {
+            polygon.push(current);
+            current = successors.remove(&current).unwrap();
+        }
+        polygons.push(polygon);
+    }
+    polygons
+}
+
+/// Splits a cell polygon into triangles, given its crossed edges and the corresponding points.
+fn push_polygon<F: ScalarField>(
+    surface: &mut Geometry,
+    polygon: &[(usize, usize)],
+    points: &[Coord3d],
+    field: &F,
+) {
+    let count = points.len();
+    match fan_apex(polygon) {
+        Some(apex) => {
+            for offset in 1..count - 1 {
+                let corners = [
+                    points[apex],
+                    points[(apex + offset) % count],
+                    points[(apex + offset + 1) % count],
+                ];
+                push_triangle(surface, corners, field);
+            }
+        }
+        None => {
+            let center = points
+                .iter()
+                .fold(Coord3d::new(0.0, 0.0, 0.0), |sum, &point| sum + point)
+                / count as f32;
+            for index in 0..count {
+                let corners = [center, points[index], points[(index + 1) % count]];
+                push_triangle(surface, corners, field);
+            }
+        }
+    }
+}
+
+/// Finds a vertex from which the polygon can be split into a fan of triangles none of which lies
+/// on a cell face. Such a triangle could coincide with one of the neighboring cell.
+fn fan_apex(polygon: &[(usize, usize)]) -> Option<usize> {
+    let is_on_face =
+        |face: &[usize; 4], (a, b): (usize, usize)| face.contains(&a) && face.contains(&b);
+    let is_flat = |edges: [(usize, usize); 3]| {
+        CUBE_FACES
+            .iter()
+            .any(|face| edges.iter().all(|&edge| is_on_face(face, edge)))
+    };
+    let count = polygon.len();
+    (0..count).find(|&apex| {
+        (1..count - 1).all(|offset| {
+            !is_flat([
+                polygon[apex],
+                polygon[(apex + offset) % count],
+                polygon[(apex + offset + 1) % count],
+            ])
+        })
+    })
+}
+
+fn push_triangle<F: ScalarField>(surface: &mut Geometry, corners: [Coord3d; 3], field: &F) {
+    let [v1, v2, v3] = corners;
+    let face_normal = (v3 - v1).cross(v3 - v2);
+    if face_normal.magnitude2() == 0.0 {
+        // The iso value equals a sample value, collapsing the triangle.
+        return;
+    }
+    for &corner in &corners {
+        let gradient = field.gradient(corner);
+        let normal = if gradient.magnitude2() > 0.0 && gradient.magnitude2().is_finite() {
+            gradient.normalize()
+        } else {
+            face_normal.normalize()
+        };
+        surface.vertices.push(VertexAttribute::new(corner, normal));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation;
+
+    fn cube_bounds(half_size: f32) -> Aabb {
+        Aabb {
+            min: Coord3d::new(-half_size, -half_size, -half_size),
+            max: Coord3d::new(half_size, half_size, half_size),
+        }
+    }
+
+    fn sphere(position: Coord3d) -> f32 {
+        position.magnitude() - 0.8
+    }
+
+    #[test]
+    fn sphere_is_closed_and_faces_outward() {
+        let grid = ScalarGrid::sample(&sphere, cube_bounds(1.0), [17, 13, 11]);
+        let surface = marching_cubes(&grid, 0.0);
+        assert!(!surface.vertices.is_empty());
+        let report = validation::validate(&surface, 1e-6);
+        assert!(report.is_watertight(), "{:?}", report.issues);
+        assert!(report.is_consistently_oriented(), "{:?}", report.issues);
+
+        for vertex in &surface.vertices {
+            let position = vertex.position();
+            assert!((position.magnitude() - 0.8).abs() < 0.02);
+            assert!(vertex.normal().dot(position.normalize()) > 0.9);
+        }
+    }
+
+    #[test]
+    fn exact_gradient_gives_exact_normals() {
+        let surface = Geometry::from_isosurface(&sphere, cube_bounds(1.0), [9, 9, 9], 0.0);
+        for vertex in &surface.vertices {
+            let expected = vertex.position().normalize();
+            assert!((vertex.normal() - expected).magnitude() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn field_without_crossing_gives_no_surface() {
+        let grid = ScalarGrid::sample(&|_: Coord3d| 1.0, cube_bounds(1.0), [4, 4, 4]);
+        assert!(marching_cubes(&grid, 0.0).vertices.is_empty());
+    }
+
+    #[test]
+    fn samples_are_stored_x_major() {
+        let field = |position: Coord3d| position.x + 10.0 * position.y + 100.0 * position.z;
+        let bounds = Aabb {
+            min: Coord3d::new(0.0, 0.0, 0.0),
+            max: Coord3d::new(1.0, 2.0, 3.0),
+        };
+        let grid = ScalarGrid::sample(&field, bounds, [2, 3, 4]);
+        assert_eq!(grid.samples(), [2, 3, 4]);
+        assert_eq!(grid.values().len(), 24);
+        assert_eq!(grid.values()[1], 1.0);
+        assert_eq!(grid.values()[2], 10.0);
+        assert_eq!(grid.values()[6], 100.0);
+        assert_eq!(grid.at([1, 2, 3]), 1.0 + 20.0 + 300.0);
+        assert_eq!(grid.spacing(), Coord3d::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least two samples")]
+    fn single_sample_grid_panics() {
+        ScalarGrid::sample(&sphere, cube_bounds(1.0), [1, 5, 5]);
+    }
+}
@@ -1,10 +1,26 @@
+pub mod bounds;
+pub mod convex_hull;
 pub mod geometry;
+pub mod gltf;
+pub mod half_edge;
+pub mod hedgehog;
+pub mod isosurface;
 pub mod load;
+pub mod lod;
 pub mod main_loop;
 pub mod manifold;
 pub mod navigator;
+pub mod obj;
+pub mod ply;
+pub mod polygon;
 pub mod prelude;
+pub mod raycast;
 pub mod render;
 pub mod shaders;
+pub mod simplify;
 pub mod solids;
+pub mod stl;
+pub mod subdivision;
 pub mod transform;
+pub mod validation;
+pub mod wireframe;
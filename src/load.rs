@@ -1,12 +1,15 @@
-use crate::prelude::*;
 use glium::backend::glutin::Display;
+use glium::IndexBuffer;
 use glium::VertexBuffer;
 
 pub trait LoadOnGpu: Sized {
+    type Vertex: Copy;
+
     fn load(self, facade: &Display) -> GpuObjectHandle<Self>;
 }
 
-pub struct GpuObjectHandle<O> {
-    pub buffer: VertexBuffer<VertexAttribute>,
+pub struct GpuObjectHandle<O: LoadOnGpu> {
+    pub buffer: VertexBuffer<O::Vertex>,
+    pub index_buffer: Option<IndexBuffer<u32>>,
     pub loaded_object: O,
 }
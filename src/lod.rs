@@ -0,0 +1,351 @@
+use crate::bounds::BoundingSphere;
+use crate::geometry::Geometry;
+use crate::geometry::QuadTriangulation;
+use crate::manifold::Manifold2d;
+use crate::prelude::*;
+use crate::simplify;
+use crate::simplify::SimplifyOptions;
+use cgmath::InnerSpace;
+use cgmath::Rad;
+
+pub struct LodLevel<O> {
+    /// The level is used as long as the object covers at least this fraction of the viewport
+    /// height, see `projected_size`.
+    pub min_screen_size: f32,
+    pub object: O,
+}
+
+/// Versions of the same object with decreasing detail.
+pub struct LodChain<O> {
+    /// Ordered from the most to the least detailed level.
+    pub levels: Vec<LodLevel<O>>,
+    /// Encloses the most detailed level. Used to estimate the screen size of the object.
+    pub bounding_sphere: BoundingSphere,
+}
+
+impl<O> LodChain<O> {
+    /// Returns the most detailed level whose `min_screen_size` is reached by `screen_size`.
+    /// Objects smaller than every threshold receive the least detailed level.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chain has no levels.
+    pub fn select(&self, screen_size: f32) -> &O {
+        let level = self
+            .levels
+            .iter()
+            .find(|level| screen_size >= level.min_screen_size)
+            .or_else(|| self.levels.last())
+            .expect("A LOD chain needs at least one level");
+        &level.object
+    }
+
+    /// Like `select`, but estimates the screen size of the object drawn with `total_transform`.
+    pub fn select_for(&self, total_transform: &Trans4d) -> &O {
+        self.select(projected_size(&self.bounding_sphere, total_transform))
+    }
+
+    /// Converts every level, e.g. to load the levels on the GPU:
+    /// `chain.map(|geometry| geometry.load(display))`.
+    pub fn map<P, F: FnMut(O) -> P>(self, mut convert: F) -> LodChain<P> {
+        LodChain {
+            levels: self
+                .levels
+                .into_iter()
+                .map(|level| LodLevel {
+                    min_screen_size: level.min_screen_size,
+                    object: convert(level.object),
+                })
+                .collect(),
+            bounding_sphere: self.bounding_sphere,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct LodOptions {
+    /// The number of levels including the original geometry.
+    pub levels: usize,
+    /// Each level has at most this fraction of the triangles of the previous one.
+    pub reduction: f32,
+    /// The screen size below which the first simplified level is used. The thresholds of further
+    /// levels shrink by `reduction.sqrt()` each, which keeps the triangles at a similar size on
+    /// screen.
+    pub first_threshold: f32,
+    /// The normals of the simplified levels are smoothed across edges flatter than this angle.
+    pub crease_angle: Rad<f32>,
+}
+
+impl LodChain<Geometry> {
+    /// Builds a chain of simplifications of `geometry`, see `simplify::simplify`. Every level is
+    /// simplified from the original geometry rather than from the previous level, so the error
+    /// does not accumulate across levels at the cost of a longer build.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `geometry` is not a `TrianglesList` or `options.levels` is zero.
+    pub fn from_geometry(geometry: Geometry, options: &LodOptions) -> LodChain<Geometry> {
+        assert!(options.levels > 0, "A LOD chain needs at least one level");
+        let bounding_sphere = bounding_sphere_of(&geometry);
+        let mut triangles = geometry.vertices.len() / 3;
+        let mut levels = vec![geometry];
+        for _ in 1..options.levels {
+            triangles = (triangles as f32 * options.reduction) as usize;
+            let simplify_options = SimplifyOptions {
+                target_triangles: triangles,
+                max_error: f32::INFINITY,
+                crease_angle: options.crease_angle,
+            };
+            let simplified = simplify::simplify(&levels[0], &simplify_options);
+            levels.push(simplified);
+        }
+
+        LodChain {
+            levels: with_thresholds(levels, options.first_threshold, options.reduction.sqrt()),
+            bounding_sphere,
+        }
+    }
+
+    /// Builds a chain of `levels` triangulations of `manifold`. Each level keeps every other
+    /// grid line of the previous one, i.e. about a quarter of its triangles. The screen size
+    /// thresholds start at `first_threshold` and halve with every level.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `levels` is zero or not less than the number of bits of `usize`, which would
+    /// overflow the subsampling step.
+    pub fn from_manifold<M: Manifold2d<Coord = Coord3d>>(
+        manifold: M,
+        levels: usize,
+        first_threshold: f32,
+    ) -> LodChain<Geometry> {
+        LodChain::from_manifold_with(
+            manifold,
+            levels,
+            first_threshold,
+            QuadTriangulation::CenterFan,
+        )
+    }
+
+    /// Like `from_manifold` but triangulates all levels with `triangulation`, see
+    /// `Geometry::from_manifold_with`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `from_manifold`.
+    pub fn from_manifold_with<M: Manifold2d<Coord = Coord3d>>(
+        manifold: M,
+        levels: usize,
+        first_threshold: f32,
+        triangulation: QuadTriangulation,
+    ) -> LodChain<Geometry> {
+        assert!(levels > 0, "A LOD chain needs at least one level");
+        assert!(
+            levels < usize::BITS as usize,
+            "A LOD chain of a manifold can have at most {} levels",
+            usize::BITS - 1
+        );
+        let levels = (0..levels)
+            .map(|level| {
+                Geometry::from_manifold_with(
+                    manifold.by_ref().subsampled(1 << level),
+                    triangulation,
+                )
+            })
+            .collect::<Vec<_>>();
+        let bounding_sphere = bounding_sphere_of(&levels[0]);
+
+        LodChain {
+            levels: with_thresholds(levels, first_threshold, 0.5),
+            bounding_sphere,
+        }
+    }
+}
+
+/// Estimates the fraction of the viewport height covered by the diameter of `sphere` when drawn
+/// with `total_transform`, the product of a perspective projection and a model-view transform
+/// without shear, e.g. the result of `transform::fix_aspect_ratio_y`.
+///
+/// Returns infinity if the viewer is inside of the sphere.
+pub fn projected_size(sphere: &BoundingSphere, total_transform: &Trans4d) -> f32 {
+    let clip_center = total_transform * sphere.center.extend(1.0);
+    let y_row = Coord3d::new(
+        total_transform[0][1],
+        total_transform[1][1],
+        total_transform[2][1],
+    );
+    let w_row = Coord3d::new(
+        total_transform[0][3],
+        total_transform[1][3],
+        total_transform[2][3],
+    );
+    // The length of the w row is the scale of the model-view transform.
+    if clip_center.w <= sphere.radius * w_row.magnitude() {
+        return f32::INFINITY;
+    }
+    sphere.radius * y_row.magnitude() / clip_center.w
+}
+
+fn bounding_sphere_of(geometry: &Geometry) -> BoundingSphere {
+    BoundingSphere::from_geometry(geometry).unwrap_or(BoundingSphere {
+        center: Coord3d::new(0.0, 0.0, 0.0),
+        radius: 0.0,
+    })
+}
+
+fn with_thresholds<O>(objects: Vec<O>, first_threshold: f32, factor: f32) -> Vec<LodLevel<O>> {
+    let number_of_levels = objects.len();
+    objects
+        .into_iter()
+        .enumerate()
+        .map(|(level, object)| LodLevel {
+            min_screen_size: if level + 1 == number_of_levels {
+                0.0
+            } else {
+                first_threshold * factor.powi(level as i32)
+            },
+            object,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounds::Aabb;
+    use crate::manifold;
+    use cgmath::Deg;
+    use cgmath::Matrix4;
+
+    fn sphere() -> Geometry {
+        let bounds = Aabb {
+            min: Coord3d::new(-1.2, -1.2, -1.2),
+            max: Coord3d::new(1.2, 1.2, 1.2),
+        };
+        let distance = |position: Coord3d| position.magnitude() - 1.0;
+        Geometry::from_isosurface(&distance, bounds, [16, 16, 16], 0.0)
+    }
+
+    fn options(levels: usize) -> LodOptions {
+        LodOptions {
+            levels,
+            reduction: 0.5,
+            first_threshold: 0.5,
+            crease_angle: Deg(60.0).into(),
+        }
+    }
+
+    #[test]
+    fn simplified_levels_meet_their_targets() {
+        let geometry = sphere();
+        let original_triangles = geometry.vertices.len() / 3;
+        let options = options(4);
+        let chain = LodChain::from_geometry(geometry, &options);
+        assert_eq!(chain.levels.len(), 4);
+
+        let mut target = original_triangles;
+        for level in &chain.levels[1..] {
+            target = (target as f32 * options.reduction) as usize;
+            let triangles = level.object.vertices.len() / 3;
+            assert!(triangles <= target);
+            assert!(triangles > 0);
+        }
+    }
+
+    #[test]
+    fn thresholds_shrink_and_end_at_zero() {
+        let options = LodOptions {
+            levels: 3,
+            reduction: 0.25,
+            first_threshold: 0.4,
+            crease_angle: Deg(60.0).into(),
+        };
+        let chain = LodChain::from_geometry(sphere(), &options);
+        let thresholds = chain
+            .levels
+            .iter()
+            .map(|level| level.min_screen_size)
+            .collect::<Vec<_>>();
+        assert_eq!(thresholds, vec![0.4, 0.2, 0.0]);
+
+        assert_eq!(
+            chain.select(1.0).vertices.len(),
+            chain.levels[0].object.vertices.len()
+        );
+        assert_eq!(
+            chain.select(0.3).vertices.len(),
+            chain.levels[1].object.vertices.len()
+        );
+        assert_eq!(
+            chain.select(0.0).vertices.len(),
+            chain.levels[2].object.vertices.len()
+        );
+    }
+
+    #[test]
+    fn manifold_levels_quarter_the_triangles() {
+        let triangle_counts = |chain: LodChain<Geometry>| {
+            chain
+                .levels
+                .iter()
+                .map(|level| level.object.vertices.len() / 3)
+                .collect::<Vec<_>>()
+        };
+        let chain = LodChain::from_manifold(manifold::torus(32, 16, 0.25), 3, 0.5);
+        assert_eq!(
+            triangle_counts(chain),
+            vec![32 * 16 * 4, 16 * 8 * 4, 8 * 4 * 4]
+        );
+        let chain = LodChain::from_manifold_with(
+            manifold::torus(32, 16, 0.25),
+            3,
+            0.5,
+            QuadTriangulation::FixedDiagonal,
+        );
+        assert_eq!(
+            triangle_counts(chain),
+            vec![32 * 16 * 2, 16 * 8 * 2, 8 * 4 * 2]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one level")]
+    fn geometry_chain_needs_a_level() {
+        LodChain::from_geometry(sphere(), &options(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "at most")]
+    fn manifold_chain_rejects_overflowing_steps() {
+        LodChain::from_manifold(manifold::torus(4, 4, 0.25), usize::BITS as usize, 0.5);
+    }
+
+    #[test]
+    fn sphere_in_front_of_the_camera() {
+        // With a vertical field of view of 90 degrees, the focal length is 1, so a sphere of
+        // radius r at distance d covers 2 * r / d of the 2 units of viewport height.
+        let projection = cgmath::perspective(Deg(90.0), 1.5, 0.1, 100.0);
+        let sphere = BoundingSphere {
+            center: Coord3d::new(3.0, 0.0, 0.0),
+            radius: 0.5,
+        };
+        let view = Matrix4::from_translation(Coord3d::new(-3.0, 0.0, -10.0));
+        let size = projected_size(&sphere, &(projection * view));
+        assert!((size - 2.0 * 0.5 / (2.0 * 10.0)).abs() < 1e-6, "{}", size);
+
+        // Scaling the model-view transform scales the sphere.
+        let scaled = view * Matrix4::from_scale(2.0);
+        let size = projected_size(&sphere, &(projection * scaled));
+        assert!((size - 2.0 * 1.0 / (2.0 * 10.0)).abs() < 1e-6, "{}", size);
+    }
+
+    #[test]
+    fn viewer_inside_the_sphere_sees_infinite_size() {
+        let sphere = BoundingSphere {
+            center: Coord3d::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let projection = cgmath::perspective(Deg(60.0), 1.0, 0.1, 100.0);
+        assert_eq!(projected_size(&sphere, &projection), f32::INFINITY);
+    }
+}
@@ -8,7 +8,7 @@ pub trait Manifold2d: Sized {
     fn height(&self) -> usize;
     fn get(&self, x: usize, y: usize) -> Self::Coord;
 
-    fn by_ref(&self) -> ByRef2d<Self> {
+    fn by_ref(&self) -> ByRef2d<'_, Self> {
         ByRef2d {
             orig_manifold: self,
         }
@@ -23,6 +23,20 @@ pub trait Manifold2d: Sized {
             transform: Box::new(transform),
         }
     }
+
+    /// Keeps every `step`-th grid line in each direction. The last grid lines are always kept, so
+    /// that the subsampled manifold covers the same surface.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `step` is zero.
+    fn subsampled(self, step: usize) -> Subsampled<Self> {
+        assert!(step > 0, "The step must be positive");
+        Subsampled {
+            orig_manifold: self,
+            step,
+        }
+    }
 }
 
 pub fn from_fn<T, F>(width: usize, height: usize, function: F) -> FromFn<T>
@@ -39,7 +53,7 @@ where
 pub struct FromFn<T> {
     width: usize,
     height: usize,
-    function: Box<Fn(usize, usize) -> T>,
+    function: Box<dyn Fn(usize, usize) -> T>,
 }
 
 impl<T> Manifold2d for FromFn<T> {
@@ -80,7 +94,7 @@ impl<'a, M: Manifold2d> Manifold2d for ByRef2d<'a, M> {
 
 pub struct Transformed<M: Manifold2d, T> {
     orig_manifold: M,
-    transform: Box<Fn(M::Coord) -> T>,
+    transform: Box<dyn Fn(M::Coord) -> T>,
 }
 
 impl<M: Manifold2d, T> Manifold2d for Transformed<M, T> {
@@ -99,6 +113,37 @@ impl<M: Manifold2d, T> Manifold2d for Transformed<M, T> {
     }
 }
 
+pub struct Subsampled<M> {
+    orig_manifold: M,
+    step: usize,
+}
+
+impl<M: Manifold2d> Manifold2d for Subsampled<M> {
+    type Coord = M::Coord;
+
+    fn width(&self) -> usize {
+        subsampled_size(self.orig_manifold.width(), self.step)
+    }
+
+    fn height(&self) -> usize {
+        subsampled_size(self.orig_manifold.height(), self.step)
+    }
+
+    fn get(&self, x: usize, y: usize) -> Self::Coord {
+        let orig_x = (x * self.step).min(self.orig_manifold.width() - 1);
+        let orig_y = (y * self.step).min(self.orig_manifold.height() - 1);
+        self.orig_manifold.get(orig_x, orig_y)
+    }
+}
+
+/// Every `step`-th grid line plus the last one, so that the subsampled grid spans the same area.
+fn subsampled_size(size: usize, step: usize) -> usize {
+    match size {
+        0 => 0,
+        size => (size - 1).div_ceil(step) + 1,
+    }
+}
+
 pub fn sphere(phi_grid: usize, theta_grid: usize) -> FromFn<Coord3d> {
     fn sphere_function(u: usize, v: usize, phi_grid: f32, theta_grid: f32) -> Coord3d {
         let phi = 2.0 * PI * u as f32 / phi_grid;
@@ -140,3 +185,33 @@ pub fn torus(phi_grid: usize, theta_grid: usize, thickness: f32) -> FromFn<Coord
     let function = move |x, y| torus_function(x, y, phi_grid as f32, theta_grid as f32, thickness);
     from_fn(phi_grid + 1, theta_grid + 1, function)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subsampling_keeps_the_last_grid_lines() {
+        let manifold = from_fn(5, 4, |x, y| (x, y)).subsampled(2);
+        assert_eq!(manifold.width(), 3);
+        assert_eq!(manifold.height(), 3);
+        assert_eq!(manifold.get(2, 1), (4, 2));
+        assert_eq!(manifold.get(2, 2), (4, 3));
+    }
+
+    #[test]
+    fn subsampling_an_empty_manifold_is_empty() {
+        for &step in [1, 2, 5].iter() {
+            let manifold = from_fn(0, 0, |x, y| (x, y)).subsampled(step);
+            assert_eq!(manifold.width(), 0);
+            assert_eq!(manifold.height(), 0);
+        }
+    }
+
+    #[test]
+    fn subsampling_a_single_grid_line_keeps_it() {
+        let manifold = from_fn(1, 7, |x, y| (x, y)).subsampled(3);
+        assert_eq!(manifold.width(), 1);
+        assert_eq!(manifold.height(), 3);
+    }
+}
@@ -50,6 +50,11 @@ impl Navigator {
         Trans4d::from_translation(Coord3d::new(0.0, 0.0, -self.distance)) * rotation
     }
 
+    /// The last cursor position in logical pixels relative to the top left corner of the window.
+    pub fn mouse_position(&self) -> (f64, f64) {
+        self.mouse_position
+    }
+
     pub fn handle_event(&mut self, event: Event) {
         if let Event::WindowEvent { event, .. } = event {
             match event {
@@ -103,7 +108,7 @@ impl Navigator {
                 / self.pixels_per_revolution as f32;
             self.theta += 2.0 * PI * (old_position.1 - new_position.1) as f32
                 / self.pixels_per_revolution as f32;
-            self.theta = self.theta.min(PI).max(0.0);
+            self.theta = self.theta.clamp(0.0, PI);
             self.rotation_start_position = Some(new_position);
         }
         if let Some(old_position) = self.translation_start_position {
@@ -0,0 +1,630 @@
+use crate::geometry;
+use crate::geometry::Geometry;
+use crate::manifold::Manifold2d;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use glium::index::PrimitiveType;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::str::SplitWhitespace;
+
+/// A Wavefront OBJ model. All indices are zero-based and refer to the shared attribute lists.
+pub struct Obj {
+    pub positions: Vec<Coord3d>,
+    pub normals: Vec<Coord3d>,
+    pub tex_coords: Vec<Coord2d>,
+    pub groups: Vec<ObjGroup>,
+}
+
+/// The faces and lines following a `g` or `o` statement.
+pub struct ObjGroup {
+    pub name: String,
+    pub faces: Vec<Vec<ObjIndex>>,
+    pub lines: Vec<Vec<ObjIndex>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ObjIndex {
+    pub position: usize,
+    pub tex_coord: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(err) => write!(f, "Could not read OBJ data: {}", err),
+            ObjError::Parse { line, message } => write!(f, "Line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io(err) => Some(err),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for ObjError {
+    fn from(err: io::Error) -> Self {
+        ObjError::Io(err)
+    }
+}
+
+impl Obj {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Obj, ObjError> {
+        Obj::parse(BufReader::new(File::open(path)?))
+    }
+
+    /// Parses `v`, `vn`, `vt`, `f`, `l`, `g` and `o` statements. All other statements, e.g.
+    /// material references, are skipped.
+    pub fn parse<R: BufRead>(reader: R) -> Result<Obj, ObjError> {
+        let mut obj = Obj {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            tex_coords: Vec::new(),
+            groups: Vec::new(),
+        };
+
+        let mut statement = String::new();
+        let mut statement_line = 0;
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if statement.is_empty() {
+                statement_line = line_number + 1;
+            }
+            match line.strip_suffix('\\') {
+                Some(continued) => {
+                    statement.push_str(continued);
+                    statement.push(' ');
+                }
+                None => {
+                    statement.push_str(&line);
+                    obj.parse_statement(&statement)
+                        .map_err(|message| ObjError::Parse {
+                            line: statement_line,
+                            message,
+                        })?;
+                    statement.clear();
+                }
+            }
+        }
+        obj.parse_statement(&statement)
+            .map_err(|message| ObjError::Parse {
+                line: statement_line,
+                message,
+            })?;
+
+        Ok(obj)
+    }
+
+    fn parse_statement(&mut self, statement: &str) -> Result<(), String> {
+        let statement = match statement.find('#') {
+            Some(comment_start) => &statement[..comment_start],
+            None => statement,
+        };
+        let mut tokens = statement.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let x = parse_next(&mut tokens, "x coordinate")?;
+                let y = parse_next(&mut tokens, "y coordinate")?;
+                let z = parse_next(&mut tokens, "z coordinate")?;
+                self.positions.push(Coord3d::new(x, y, z));
+            }
+            Some("vn") => {
+                let x = parse_next(&mut tokens, "x component")?;
+                let y = parse_next(&mut tokens, "y component")?;
+                let z = parse_next(&mut tokens, "z component")?;
+                self.normals.push(Coord3d::new(x, y, z));
+            }
+            Some("vt") => {
+                let u = parse_next(&mut tokens, "u coordinate")?;
+                let v = tokens.next().map(parse_number).unwrap_or(Ok(0.0))?;
+                self.tex_coords.push(Coord2d::new(u, v));
+            }
+            Some("f") => {
+                let face = self.parse_indices(tokens)?;
+                if face.len() < 3 {
+                    return Err(format!(
+                        "Face has {} vertices, expected 3 or more",
+                        face.len()
+                    ));
+                }
+                self.current_group().faces.push(face);
+            }
+            Some("l") => {
+                let line = self.parse_indices(tokens)?;
+                if line.len() < 2 {
+                    return Err(format!(
+                        "Line has {} vertices, expected 2 or more",
+                        line.len()
+                    ));
+                }
+                self.current_group().lines.push(line);
+            }
+            Some("g") | Some("o") => {
+                let name = tokens.collect::<Vec<_>>().join(" ");
+                self.groups.push(ObjGroup::new(name));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn parse_indices(&self, tokens: SplitWhitespace) -> Result<Vec<ObjIndex>, String> {
+        tokens
+            .map(|token| {
+                let mut parts = token.split('/');
+                let position = parts.next().unwrap_or("");
+                let tex_coord = parts.next().filter(|part| !part.is_empty());
+                let normal = parts.next().filter(|part| !part.is_empty());
+                if parts.next().is_some() {
+                    return Err(format!("Invalid vertex reference '{}'", token));
+                }
+                Ok(ObjIndex {
+                    position: resolve_index(position, self.positions.len(), "position")?,
+                    tex_coord: tex_coord
+                        .map(|index| {
+                            resolve_index(index, self.tex_coords.len(), "texture coordinate")
+                        })
+                        .transpose()?,
+                    normal: normal
+                        .map(|index| resolve_index(index, self.normals.len(), "normal"))
+                        .transpose()?,
+                })
+            })
+            .collect()
+    }
+
+    fn current_group(&mut self) -> &mut ObjGroup {
+        if self.groups.is_empty() {
+            self.groups.push(ObjGroup::new(String::from("default")));
+        }
+        self.groups.last_mut().unwrap()
+    }
+
+    /// Triangulates the faces of all groups. Polygons are split into triangle fans which is only
+    /// correct for convex polygons. Triangles without vertex normals receive their face normal.
+    pub fn to_geometry(&self) -> Geometry {
+        let mut geometry = Geometry::new(PrimitiveType::TrianglesList);
+        for group in &self.groups {
+            self.append_triangles(group, &mut geometry);
+        }
+        geometry
+    }
+
+    pub fn group_to_geometry(&self, group: &ObjGroup) -> Geometry {
+        let mut geometry = Geometry::new(PrimitiveType::TrianglesList);
+        self.append_triangles(group, &mut geometry);
+        geometry
+    }
+
+    /// Like `to_geometry` but also emits texture coordinates. Vertices without a texture coordinate
+    /// are mapped to `(0, 0)`.
+    pub fn to_textured_geometry(&self) -> Geometry<TexVertexAttribute> {
+        let mut geometry = Geometry::empty(PrimitiveType::TrianglesList);
+        for group in &self.groups {
+            self.append_textured_triangles(group, &mut geometry);
+        }
+        geometry
+    }
+
+    pub fn group_to_textured_geometry(&self, group: &ObjGroup) -> Geometry<TexVertexAttribute> {
+        let mut geometry = Geometry::empty(PrimitiveType::TrianglesList);
+        self.append_textured_triangles(group, &mut geometry);
+        geometry
+    }
+
+    /// Converts the polylines of all groups into a `LinesList`.
+    pub fn to_line_geometry(&self) -> Geometry {
+        let mut geometry = Geometry::new(PrimitiveType::LinesList);
+        for group in &self.groups {
+            for line in &group.lines {
+                for segment in line.windows(2) {
+                    for index in segment {
+                        geometry.vertices.push(VertexAttribute::without_normal(
+                            self.positions[index.position],
+                        ));
+                    }
+                }
+            }
+        }
+        geometry
+    }
+
+    /// Converts `geometry` into a single group. Triangle primitives become faces with normals and
+    /// line primitives become polylines. With `weld_tolerance` set, positions and normals closer
+    /// than the tolerance are shared so that the faces are connected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `geometry` consists of points or adjacency primitives.
+    pub fn from_geometry(geometry: &Geometry, weld_tolerance: Option<f32>) -> Obj {
+        let weld = |values: Vec<Coord3d>| match weld_tolerance {
+            Some(tolerance) => geometry::weld_positions(values, tolerance),
+            None => {
+                let indices = (0..values.len() as u32).collect();
+                (values, indices)
+            }
+        };
+        let (positions, position_indices) = weld(
+            geometry
+                .vertices
+                .iter()
+                .map(VertexAttribute::position)
+                .collect(),
+        );
+        let (normals, normal_indices) = weld(
+            geometry
+                .vertices
+                .iter()
+                .map(VertexAttribute::normal)
+                .collect(),
+        );
+
+        let face_index = |i: usize| ObjIndex {
+            position: position_indices[i] as usize,
+            tex_coord: None,
+            normal: Some(normal_indices[i] as usize),
+        };
+        let line_index = |i: usize| ObjIndex {
+            position: position_indices[i] as usize,
+            tex_coord: None,
+            normal: None,
+        };
+
+        let number_of_vertices = geometry.vertices.len();
+        let mut group = ObjGroup::new(String::from("default"));
+        match geometry.primitive_type {
+            PrimitiveType::TrianglesList => {
+                for i in (0..number_of_vertices / 3 * 3).step_by(3) {
+                    group.faces.push((i..i + 3).map(face_index).collect());
+                }
+            }
+            PrimitiveType::TriangleStrip => {
+                for i in 0..number_of_vertices.saturating_sub(2) {
+                    let face = if i % 2 == 0 {
+                        [i, i + 1, i + 2]
+                    } else {
+                        [i + 1, i, i + 2]
+                    };
+                    group
+                        .faces
+                        .push(face.iter().cloned().map(face_index).collect());
+                }
+            }
+            PrimitiveType::TriangleFan => {
+                for i in 1..number_of_vertices.saturating_sub(1) {
+                    group
+                        .faces
+                        .push([0, i, i + 1].iter().cloned().map(face_index).collect());
+                }
+            }
+            PrimitiveType::LinesList => {
+                for i in (0..number_of_vertices / 2 * 2).step_by(2) {
+                    group.lines.push((i..i + 2).map(line_index).collect());
+                }
+            }
+            PrimitiveType::LineStrip if number_of_vertices >= 2 => {
+                group
+                    .lines
+                    .push((0..number_of_vertices).map(line_index).collect());
+            }
+            PrimitiveType::LineLoop if number_of_vertices >= 2 => {
+                let closed_loop = (0..number_of_vertices).chain(Some(0));
+                group.lines.push(closed_loop.map(line_index).collect());
+            }
+            PrimitiveType::LineStrip | PrimitiveType::LineLoop => {}
+            other => panic!("{:?} cannot be represented as OBJ", other),
+        }
+
+        Obj {
+            positions,
+            normals: if group.faces.is_empty() {
+                Vec::new()
+            } else {
+                normals
+            },
+            tex_coords: Vec::new(),
+            groups: vec![group],
+        }
+    }
+
+    /// Converts the grid of `manifold` into quadrilateral faces sharing their corners. The faces
+    /// have the same orientation as the triangles created by `Geometry::from_manifold`.
+    pub fn from_manifold<M: Manifold2d<Coord = Coord3d>>(manifold: M) -> Obj {
+        let width = manifold.width();
+        let height = manifold.height();
+
+        let mut positions = Vec::with_capacity(width * height);
+        for x in 0..width {
+            for y in 0..height {
+                positions.push(manifold.get(x, y));
+            }
+        }
+
+        let index = |x: usize, y: usize| ObjIndex {
+            position: x * height + y,
+            tex_coord: None,
+            normal: None,
+        };
+        let mut group = ObjGroup::new(String::from("default"));
+        for x in 0..width.saturating_sub(1) {
+            for y in 0..height.saturating_sub(1) {
+                group.faces.push(vec![
+                    index(x, y),
+                    index(x, y + 1),
+                    index(x + 1, y + 1),
+                    index(x + 1, y),
+                ]);
+            }
+        }
+
+        Obj {
+            positions,
+            normals: Vec::new(),
+            tex_coords: Vec::new(),
+            groups: vec![group],
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for position in &self.positions {
+            writeln!(writer, "v {} {} {}", position.x, position.y, position.z)?;
+        }
+        for tex_coord in &self.tex_coords {
+            writeln!(writer, "vt {} {}", tex_coord.x, tex_coord.y)?;
+        }
+        for normal in &self.normals {
+            writeln!(writer, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+        }
+        for group in &self.groups {
+            writeln!(writer, "g {}", group.name)?;
+            for face in &group.faces {
+                write_element(&mut writer, "f", face)?;
+            }
+            for line in &group.lines {
+                write_element(&mut writer, "l", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn append_triangles(&self, group: &ObjGroup, geometry: &mut Geometry) {
+        for triangle in triangle_fans(group) {
+            let positions = triangle.map(|index| self.positions[index.position]);
+            let normals = triangle.map(|index| index.normal.map(|normal| self.normals[normal]));
+            match normals {
+                [Some(n1), Some(n2), Some(n3)] => {
+                    for (&position, &normal) in positions.iter().zip([n1, n2, n3].iter()) {
+                        geometry
+                            .vertices
+                            .push(VertexAttribute::new(position, normal));
+                    }
+                }
+                _ => geometry.push_triangle(positions[0], positions[1], positions[2]),
+            }
+        }
+    }
+
+    fn append_textured_triangles(
+        &self,
+        group: &ObjGroup,
+        geometry: &mut Geometry<TexVertexAttribute>,
+    ) {
+        for triangle in triangle_fans(group) {
+            let positions = triangle.map(|index| self.positions[index.position]);
+            let face_normal = (positions[2] - positions[0])
+                .cross(positions[2] - positions[1])
+                .normalize();
+            for (index, &position) in triangle.iter().zip(positions.iter()) {
+                let normal = index
+                    .normal
+                    .map(|normal| self.normals[normal])
+                    .unwrap_or(face_normal);
+                let tex_coord = index
+                    .tex_coord
+                    .map(|tex_coord| self.tex_coords[tex_coord])
+                    .unwrap_or_else(|| Coord2d::new(0.0, 0.0));
+                geometry
+                    .vertices
+                    .push(TexVertexAttribute::new(position, normal, tex_coord));
+            }
+        }
+    }
+}
+
+impl ObjGroup {
+    fn new(name: String) -> ObjGroup {
+        ObjGroup {
+            name,
+            faces: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+}
+
+fn write_element<W: Write>(writer: &mut W, keyword: &str, indices: &[ObjIndex]) -> io::Result<()> {
+    write!(writer, "{}", keyword)?;
+    for index in indices {
+        write!(writer, " {}", index.position + 1)?;
+        match (index.tex_coord, index.normal) {
+            (Some(tex_coord), Some(normal)) => write!(writer, "/{}/{}", tex_coord + 1, normal + 1)?,
+            (Some(tex_coord), None) => write!(writer, "/{}", tex_coord + 1)?,
+            (None, Some(normal)) => write!(writer, "//{}", normal + 1)?,
+            (None, None) => {}
+        }
+    }
+    writeln!(writer)
+}
+
+fn triangle_fans<'a>(group: &'a ObjGroup) -> impl Iterator<Item = [ObjIndex; 3]> + 'a {
+    group
+        .faces
+        .iter()
+        .flat_map(|face| (1..face.len() - 1).map(move |i| [face[0], face[i], face[i + 1]]))
+}
+
+fn parse_next<T: FromStr>(tokens: &mut SplitWhitespace, what: &str) -> Result<T, String> {
+    match tokens.next() {
+        Some(token) => parse_number(token),
+        None => Err(format!("Missing {}", what)),
+    }
+}
+
+fn parse_number<T: FromStr>(token: &str) -> Result<T, String> {
+    token
+        .parse()
+        .map_err(|_| format!("Invalid number '{}'", token))
+}
+
+/// Converts a one-based or negative (relative to the end) OBJ index into a zero-based index.
+fn resolve_index(token: &str, count: usize, what: &str) -> Result<usize, String> {
+    let index: isize = parse_number(token)?;
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as isize + index
+    };
+    if index == 0 || resolved < 0 || resolved >= count as isize {
+        return Err(format!(
+            "Index {} is out of range, {} {}s defined so far",
+            index, count, what
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solids;
+
+    fn round_trip(obj: &Obj) -> Obj {
+        let mut data = Vec::new();
+        obj.write(&mut data).unwrap();
+        Obj::parse(&data[..]).unwrap()
+    }
+
+    fn assert_near(a: Coord3d, b: Coord3d) {
+        assert!((a - b).magnitude() < 1e-6, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn geometry_round_trips() {
+        let cube = solids::cube();
+        let parsed = round_trip(&Obj::from_geometry(&cube, None));
+
+        assert_eq!(parsed.groups.len(), 1);
+        assert_eq!(parsed.groups[0].faces.len(), cube.vertices.len() / 3);
+        let geometry = parsed.to_geometry();
+        assert_eq!(geometry.vertices.len(), cube.vertices.len());
+        for (parsed, original) in geometry.vertices.iter().zip(&cube.vertices) {
+            assert_near(parsed.position(), original.position());
+            assert_near(parsed.normal(), original.normal());
+        }
+    }
+
+    #[test]
+    fn welded_geometry_shares_corners() {
+        let parsed = round_trip(&Obj::from_geometry(&solids::cube(), Some(1e-5)));
+
+        assert_eq!(parsed.positions.len(), 8);
+        assert_eq!(parsed.normals.len(), 6);
+        assert_eq!(parsed.groups[0].faces.len(), 12);
+    }
+
+    #[test]
+    fn tex_coords_round_trip() {
+        let obj = Obj::parse(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+             vn 0 0 1\n\
+             f 1/1/1 2/2/1 3/3/1 4/4/1\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let geometry = round_trip(&obj).to_textured_geometry();
+
+        let tex_coords = geometry
+            .vertices
+            .iter()
+            .map(TexVertexAttribute::tex_coord)
+            .collect::<Vec<_>>();
+        let expected = [
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (1.0, 1.0),
+            (0.0, 0.0),
+            (1.0, 1.0),
+            (0.0, 1.0),
+        ];
+        assert_eq!(tex_coords.len(), expected.len());
+        for (tex_coord, &(u, v)) in tex_coords.iter().zip(expected.iter()) {
+            assert_eq!(*tex_coord, Coord2d::new(u, v));
+        }
+        for vertex in &geometry.vertices {
+            assert_eq!(vertex.position().x, vertex.tex_coord().x);
+            assert_eq!(vertex.position().y, vertex.tex_coord().y);
+            assert_eq!(vertex.normal(), Coord3d::new(0.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn parses_negative_indices_continuations_and_polygons() {
+        let obj = Obj::parse(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 2 0\n\
+             f -5 -4 -3 \\\n  -2 -1\n\
+             l 1 -1\n"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        let group = &obj.groups[0];
+        let positions = |indices: &[ObjIndex]| {
+            indices
+                .iter()
+                .map(|index| index.position)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(group.faces.len(), 1);
+        assert_eq!(positions(&group.faces[0]), [0, 1, 2, 3, 4]);
+        assert_eq!(positions(&group.lines[0]), [0, 4]);
+        assert_eq!(obj.to_geometry().vertices.len(), 9);
+
+        let parsed = round_trip(&obj);
+        assert_eq!(positions(&parsed.groups[0].faces[0]), [0, 1, 2, 3, 4]);
+        assert_eq!(parsed.positions, obj.positions);
+    }
+
+    #[test]
+    fn reports_the_line_of_invalid_indices() {
+        match Obj::parse("v 0 0 0\nv 1 0 0\n\nf 1 2 -3\n".as_bytes()) {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, 4),
+            _ => panic!("Expected a parse error"),
+        }
+    }
+}
@@ -0,0 +1,745 @@
+use crate::geometry::Geometry;
+use crate::geometry::IndexedGeometry;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use glium::index::PrimitiveType;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::str::SplitWhitespace;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// The `vertex` and `face` elements of a PLY file. Colors are scaled to the range `[0, 1]`. Vertex
+/// properties other than position, normal and color are kept in `vertex_properties`.
+pub struct PlyMesh {
+    pub positions: Vec<Coord3d>,
+    pub normals: Option<Vec<Coord3d>>,
+    pub colors: Option<Vec<Coord3d>>,
+    pub vertex_properties: Vec<(String, Vec<f32>)>,
+    pub faces: Vec<Vec<u32>>,
+}
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(io::Error),
+    Header { line: usize, message: String },
+    Data(String),
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlyError::Io(err) => write!(f, "Could not read PLY data: {}", err),
+            PlyError::Header { line, message } => write!(f, "Header line {}: {}", line, message),
+            PlyError::Data(message) => write!(f, "Invalid PLY data: {}", message),
+        }
+    }
+}
+
+impl Error for PlyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PlyError::Io(err) => Some(err),
+            PlyError::Header { .. } | PlyError::Data(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for PlyError {
+    fn from(err: io::Error) -> Self {
+        PlyError::Io(err)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+enum PropertyKind {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl PlyMesh {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PlyMesh, PlyError> {
+        PlyMesh::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads ASCII or binary PLY data. Elements other than `vertex` and `face` are skipped.
+    pub fn read<R: BufRead>(mut reader: R) -> Result<PlyMesh, PlyError> {
+        let (format, elements) = read_header(&mut reader)?;
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let text;
+        let mut body = match format {
+            PlyFormat::Ascii => {
+                text = String::from_utf8(data)
+                    .map_err(|_| PlyError::Data(String::from("ASCII body is not valid UTF-8")))?;
+                Body::Ascii(text.split_whitespace())
+            }
+            PlyFormat::BinaryLittleEndian | PlyFormat::BinaryBigEndian => Body::Binary {
+                data: &data,
+                position: 0,
+                big_endian: format == PlyFormat::BinaryBigEndian,
+            },
+        };
+
+        let mut mesh = PlyMesh {
+            positions: Vec::new(),
+            normals: None,
+            colors: None,
+            vertex_properties: Vec::new(),
+            faces: Vec::new(),
+        };
+        for element in &elements {
+            match element.name.as_str() {
+                "vertex" => mesh.read_vertices(&mut body, element)?,
+                "face" => mesh.read_faces(&mut body, element)?,
+                _ => {
+                    for _ in 0..element.count {
+                        read_row(&mut body, element)?;
+                    }
+                }
+            }
+        }
+
+        let number_of_vertices = mesh.positions.len();
+        if let Some(&index) = mesh
+            .faces
+            .iter()
+            .flatten()
+            .find(|&&index| index as usize >= number_of_vertices)
+        {
+            return Err(PlyError::Data(format!(
+                "Face refers to vertex {} but only {} vertices exist",
+                index, number_of_vertices
+            )));
+        }
+        Ok(mesh)
+    }
+
+    fn read_vertices(&mut self, body: &mut Body, element: &Element) -> Result<(), PlyError> {
+        let mut columns = vec![Vec::with_capacity(element.count); element.properties.len()];
+        for _ in 0..element.count {
+            for (column, value) in columns.iter_mut().zip(read_row(body, element)?) {
+                if let Some(value) = value.first() {
+                    column.push(*value);
+                }
+            }
+        }
+
+        let mut named_columns = element
+            .properties
+            .iter()
+            .zip(columns)
+            .filter_map(|(property, column)| match property.kind {
+                PropertyKind::Scalar(scalar_type) => {
+                    Some((property.name.as_str(), scalar_type, column))
+                }
+                PropertyKind::List { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        self.positions =
+            take_coords(&mut named_columns, ["x", "y", "z"], |_| 1.0).ok_or_else(|| {
+                PlyError::Data(String::from("Vertex element lacks an x, y or z property"))
+            })?;
+        self.normals = take_coords(&mut named_columns, ["nx", "ny", "nz"], |_| 1.0);
+        self.colors = take_coords(
+            &mut named_columns,
+            ["red", "green", "blue"],
+            ScalarType::color_scale,
+        );
+        self.vertex_properties = named_columns
+            .into_iter()
+            .map(|(name, _, column)| {
+                (
+                    String::from(name),
+                    column.into_iter().map(|value| value as f32).collect(),
+                )
+            })
+            .collect();
+        Ok(())
+    }
+
+    fn read_faces(&mut self, body: &mut Body, element: &Element) -> Result<(), PlyError> {
+        let index_property = element
+            .properties
+            .iter()
+            .position(|property| match property.kind {
+                PropertyKind::List { .. } => {
+                    property.name == "vertex_indices" || property.name == "vertex_index"
+                }
+                PropertyKind::Scalar(_) => false,
+            })
+            .ok_or_else(|| {
+                PlyError::Data(String::from("Face element lacks a vertex_indices list"))
+            })?;
+
+        for _ in 0..element.count {
+            let mut row = read_row(body, element)?;
+            let face = row.swap_remove(index_property);
+            if face.iter().any(|&index| index < 0.0) {
+                return Err(PlyError::Data(String::from("Negative vertex index")));
+            }
+            self.faces
+                .push(face.into_iter().map(|index| index as u32).collect());
+        }
+        Ok(())
+    }
+
+    /// Welds vertices of a `TrianglesList` or `Points` geometry within `weld_tolerance` and
+    /// stores their normals.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `geometry` consists of any other primitive type.
+    pub fn from_geometry(geometry: &Geometry, weld_tolerance: f32) -> PlyMesh {
+        let indexed = IndexedGeometry::from_geometry(geometry, weld_tolerance);
+        let faces = match geometry.primitive_type {
+            PrimitiveType::TrianglesList => indexed
+                .indices
+                .chunks_exact(3)
+                .map(|triangle| triangle.to_vec())
+                .collect(),
+            PrimitiveType::Points => Vec::new(),
+            other => panic!("{:?} cannot be represented as PLY", other),
+        };
+        PlyMesh {
+            positions: indexed
+                .vertices
+                .iter()
+                .map(VertexAttribute::position)
+                .collect(),
+            normals: Some(
+                indexed
+                    .vertices
+                    .iter()
+                    .map(VertexAttribute::normal)
+                    .collect(),
+            ),
+            colors: None,
+            vertex_properties: Vec::new(),
+            faces,
+        }
+    }
+
+    /// Triangulates all faces as triangle fans. Without vertex normals, each triangle receives its
+    /// face normal. A mesh without faces is converted into a point cloud.
+    pub fn to_geometry(&self) -> Geometry {
+        self.build_geometry(|_, position, normal| VertexAttribute::new(position, normal))
+    }
+
+    /// Like `to_geometry` but also emits vertex colors. Vertices without color become white.
+    pub fn to_colored_geometry(&self) -> Geometry<ColorVertexAttribute> {
+        self.build_geometry(|index, position, normal| {
+            let color = match &self.colors {
+                Some(colors) => colors[index],
+                None => Coord3d::new(1.0, 1.0, 1.0),
+            };
+            ColorVertexAttribute::new(position, normal, color)
+        })
+    }
+
+    fn build_geometry<V, F: Fn(usize, Coord3d, Coord3d) -> V>(&self, vertex: F) -> Geometry<V> {
+        if self.faces.is_empty() {
+            let mut geometry = Geometry::empty(PrimitiveType::Points);
+            for (index, &position) in self.positions.iter().enumerate() {
+                let normal = match &self.normals {
+                    Some(normals) => normals[index],
+                    None => Coord3d::new(0.0, 0.0, 0.0),
+                };
+                geometry.vertices.push(vertex(index, position, normal));
+            }
+            return geometry;
+        }
+
+        let mut geometry = Geometry::empty(PrimitiveType::TrianglesList);
+        for face in &self.faces {
+            for i in 1..face.len().saturating_sub(1) {
+                let triangle = [face[0] as usize, face[i] as usize, face[i + 1] as usize];
+                let [v1, v2, v3] = triangle.map(|index| self.positions[index]);
+                let face_normal = (v3 - v1).cross(v3 - v2).normalize();
+                for &index in triangle.iter() {
+                    let normal = match &self.normals {
+                        Some(normals) => normals[index],
+                        None => face_normal,
+                    };
+                    geometry
+                        .vertices
+                        .push(vertex(index, self.positions[index], normal));
+                }
+            }
+        }
+        geometry
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: PlyFormat) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, format)?;
+        writer.flush()
+    }
+
+    /// Writes positions, normals, colors and additional vertex properties followed by the faces.
+    /// Colors are stored as `uchar`, all other vertex properties as `float`. Face sizes are stored
+    /// as `uchar` unless a face has more than 255 vertices, in which case they are stored as `int`.
+    ///
+    /// Fails with `io::ErrorKind::InvalidInput` if a vertex attribute list does not have one entry
+    /// per position.
+    pub fn write<W: Write>(&self, mut writer: W, format: PlyFormat) -> io::Result<()> {
+        let number_of_vertices = self.positions.len();
+        let columns = self
+            .normals
+            .iter()
+            .map(|normals| ("normals", normals.len()))
+            .chain(self.colors.iter().map(|colors| ("colors", colors.len())))
+            .chain(
+                self.vertex_properties
+                    .iter()
+                    .map(|(name, values)| (&name[..], values.len())),
+            );
+        for (name, length) in columns {
+            if length != number_of_vertices {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Vertex property {} has {} values but there are {} vertices",
+                        name, length, number_of_vertices
+                    ),
+                ));
+            }
+        }
+        let (face_size_type, face_size_name) = if self.faces.iter().all(|face| face.len() <= 255) {
+            (ScalarType::UInt8, "uchar")
+        } else {
+            (ScalarType::Int32, "int")
+        };
+
+        let format_name = match format {
+            PlyFormat::Ascii => "ascii",
+            PlyFormat::BinaryLittleEndian => "binary_little_endian",
+            PlyFormat::BinaryBigEndian => "binary_big_endian",
+        };
+        writeln!(writer, "ply")?;
+        writeln!(writer, "format {} 1.0", format_name)?;
+        writeln!(writer, "comment written by cg-util")?;
+        writeln!(writer, "element vertex {}", self.positions.len())?;
+        for name in ["x", "y", "z"].iter() {
+            writeln!(writer, "property float {}", name)?;
+        }
+        if self.normals.is_some() {
+            for name in ["nx", "ny", "nz"].iter() {
+                writeln!(writer, "property float {}", name)?;
+            }
+        }
+        if self.colors.is_some() {
+            for name in ["red", "green", "blue"].iter() {
+                writeln!(writer, "property uchar {}", name)?;
+            }
+        }
+        for (name, _) in &self.vertex_properties {
+            writeln!(writer, "property float {}", name)?;
+        }
+        writeln!(writer, "element face {}", self.faces.len())?;
+        writeln!(
+            writer,
+            "property list {} int vertex_indices",
+            face_size_name
+        )?;
+        writeln!(writer, "end_header")?;
+
+        for index in 0..number_of_vertices {
+            let mut row = Vec::new();
+            let mut push_coord = |coord: Coord3d, scalar_type: ScalarType, scale: f64| {
+                for &component in [coord.x, coord.y, coord.z].iter() {
+                    row.push((scalar_type, f64::from(component) * scale));
+                }
+            };
+            push_coord(self.positions[index], ScalarType::Float32, 1.0);
+            if let Some(normals) = &self.normals {
+                push_coord(normals[index], ScalarType::Float32, 1.0);
+            }
+            if let Some(colors) = &self.colors {
+                let color = colors[index];
+                let clamped = Coord3d::new(
+                    color.x.clamp(0.0, 1.0),
+                    color.y.clamp(0.0, 1.0),
+                    color.z.clamp(0.0, 1.0),
+                );
+                push_coord(clamped, ScalarType::UInt8, 255.0);
+            }
+            for (_, values) in &self.vertex_properties {
+                row.push((ScalarType::Float32, f64::from(values[index])));
+            }
+            write_row(&mut writer, format, &row)?;
+        }
+
+        for face in &self.faces {
+            let row = Some((face_size_type, face.len() as f64))
+                .into_iter()
+                .chain(
+                    face.iter()
+                        .map(|&index| (ScalarType::Int32, f64::from(index))),
+                )
+                .collect::<Vec<_>>();
+            write_row(&mut writer, format, &row)?;
+        }
+        Ok(())
+    }
+}
+
+impl ScalarType {
+    fn from_name(name: &str) -> Option<ScalarType> {
+        let scalar_type = match name {
+            "char" | "int8" => ScalarType::Int8,
+            "uchar" | "uint8" => ScalarType::UInt8,
+            "short" | "int16" => ScalarType::Int16,
+            "ushort" | "uint16" => ScalarType::UInt16,
+            "int" | "int32" => ScalarType::Int32,
+            "uint" | "uint32" => ScalarType::UInt32,
+            "float" | "float32" => ScalarType::Float32,
+            "double" | "float64" => ScalarType::Float64,
+            _ => return None,
+        };
+        Some(scalar_type)
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    fn is_integer(self) -> bool {
+        !matches!(self, ScalarType::Float32 | ScalarType::Float64)
+    }
+
+    /// Integer colors use the full range of their type, floating point colors are already in
+    /// `[0, 1]`.
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::Int8 => 127.0,
+            ScalarType::UInt8 => 255.0,
+            ScalarType::Int16 => 32767.0,
+            ScalarType::UInt16 => 65535.0,
+            ScalarType::Int32 => 2147483647.0,
+            ScalarType::UInt32 => 4294967295.0,
+            ScalarType::Float32 | ScalarType::Float64 => 1.0,
+        }
+    }
+}
+
+enum Body<'a> {
+    Ascii(SplitWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        position: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, PlyError> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| PlyError::Data(String::from("Unexpected end of data")))?;
+                token
+                    .parse()
+                    .map_err(|_| PlyError::Data(format!("Invalid number '{}'", token)))
+            }
+            Body::Binary {
+                data,
+                position,
+                big_endian,
+            } => {
+                let size = scalar_type.size();
+                if *position + size > data.len() {
+                    return Err(PlyError::Data(String::from("Unexpected end of data")));
+                }
+                let mut bytes = [0; 8];
+                bytes[..size].copy_from_slice(&data[*position..*position + size]);
+                *position += size;
+                if *big_endian {
+                    bytes[..size].reverse();
+                }
+                let value = match scalar_type {
+                    ScalarType::Int8 => f64::from(bytes[0] as i8),
+                    ScalarType::UInt8 => f64::from(bytes[0]),
+                    ScalarType::Int16 => f64::from(i16::from_le_bytes([bytes[0], bytes[1]])),
+                    ScalarType::UInt16 => f64::from(u16::from_le_bytes([bytes[0], bytes[1]])),
+                    ScalarType::Int32 => {
+                        f64::from(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    }
+                    ScalarType::UInt32 => {
+                        f64::from(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    }
+                    ScalarType::Float32 => {
+                        f64::from(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    }
+                    ScalarType::Float64 => f64::from_le_bytes(bytes),
+                };
+                Ok(value)
+            }
+        }
+    }
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> Result<(PlyFormat, Vec<Element>), PlyError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    let mut line = String::new();
+    for line_number in 1.. {
+        let header_error = |message: String| PlyError::Header {
+            line: line_number,
+            message,
+        };
+
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(header_error(String::from("Missing end_header")));
+        }
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        if line_number == 1 {
+            if tokens != ["ply"] {
+                return Err(header_error(String::from("Missing magic number 'ply'")));
+            }
+            continue;
+        }
+
+        match tokens[..] {
+            ["format", name, _version] => {
+                format = Some(match name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(header_error(format!("Unknown format '{}'", name))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: String::from(name),
+                count: count
+                    .parse()
+                    .map_err(|_| header_error(format!("Invalid element count '{}'", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => {
+                let kind = PropertyKind::List {
+                    count: parse_scalar_type(count_type).map_err(header_error)?,
+                    item: parse_scalar_type(item_type).map_err(header_error)?,
+                };
+                push_property(&mut elements, name, kind).map_err(header_error)?;
+            }
+            ["property", scalar_type, name] => {
+                let kind =
+                    PropertyKind::Scalar(parse_scalar_type(scalar_type).map_err(header_error)?);
+                push_property(&mut elements, name, kind).map_err(header_error)?;
+            }
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => {
+                return Err(header_error(format!(
+                    "Invalid header line '{}'",
+                    line.trim()
+                )))
+            }
+        }
+    }
+
+    match format {
+        Some(format) => Ok((format, elements)),
+        None => Err(PlyError::Header {
+            line: 1,
+            message: String::from("Missing format declaration"),
+        }),
+    }
+}
+
+/// Removes the three named columns and combines them into coordinates. Returns `None` unless all
+/// three columns exist.
+fn take_coords<F: Fn(ScalarType) -> f64>(
+    columns: &mut Vec<(&str, ScalarType, Vec<f64>)>,
+    names: [&str; 3],
+    scale: F,
+) -> Option<Vec<Coord3d>> {
+    let positions = names
+        .iter()
+        .map(|name| columns.iter().position(|column| column.0 == *name))
+        .collect::<Option<Vec<_>>>()?;
+    let mut components = positions.iter().map(|&index| {
+        let (_, scalar_type, values) = &columns[index];
+        let scale = scale(*scalar_type);
+        values
+            .iter()
+            .map(|&value| (value / scale) as f32)
+            .collect::<Vec<_>>()
+    });
+    let (x, y, z) = (components.next()?, components.next()?, components.next()?);
+    columns.retain(|column| !names.contains(&column.0));
+
+    let coords = x
+        .into_iter()
+        .zip(y)
+        .zip(z)
+        .map(|((x, y), z)| Coord3d::new(x, y, z))
+        .collect();
+    Some(coords)
+}
+
+fn parse_scalar_type(name: &str) -> Result<ScalarType, String> {
+    ScalarType::from_name(name).ok_or_else(|| format!("Unknown property type '{}'", name))
+}
+
+fn push_property(elements: &mut [Element], name: &str, kind: PropertyKind) -> Result<(), String> {
+    let element = elements
+        .last_mut()
+        .ok_or_else(|| String::from("Property declared before any element"))?;
+    element.properties.push(Property {
+        name: String::from(name),
+        kind,
+    });
+    Ok(())
+}
+
+/// Reads one row of `element`. Scalar properties yield a single value, list properties all items.
+fn read_row(body: &mut Body, element: &Element) -> Result<Vec<Vec<f64>>, PlyError> {
+    element
+        .properties
+        .iter()
+        .map(|property| match property.kind {
+            PropertyKind::Scalar(scalar_type) => Ok(vec![body.read(scalar_type)?]),
+            PropertyKind::List { count, item } => {
+                let count = body.read(count)?;
+                (0..count as usize).map(|_| body.read(item)).collect()
+            }
+        })
+        .collect()
+}
+
+fn write_row<W: Write>(
+    writer: &mut W,
+    format: PlyFormat,
+    row: &[(ScalarType, f64)],
+) -> io::Result<()> {
+    if format == PlyFormat::Ascii {
+        let tokens = row
+            .iter()
+            .map(|&(scalar_type, value)| {
+                if scalar_type.is_integer() {
+                    (value.round() as i64).to_string()
+                } else {
+                    (value as f32).to_string()
+                }
+            })
+            .collect::<Vec<_>>();
+        return writeln!(writer, "{}", tokens.join(" "));
+    }
+
+    for &(scalar_type, value) in row {
+        let mut bytes = match scalar_type {
+            ScalarType::Int8 => (value.round() as i8).to_le_bytes().to_vec(),
+            ScalarType::UInt8 => (value.round() as u8).to_le_bytes().to_vec(),
+            ScalarType::Int16 => (value.round() as i16).to_le_bytes().to_vec(),
+            ScalarType::UInt16 => (value.round() as u16).to_le_bytes().to_vec(),
+            ScalarType::Int32 => (value.round() as i32).to_le_bytes().to_vec(),
+            ScalarType::UInt32 => (value.round() as u32).to_le_bytes().to_vec(),
+            ScalarType::Float32 => (value as f32).to_le_bytes().to_vec(),
+            ScalarType::Float64 => value.to_le_bytes().to_vec(),
+        };
+        if format == PlyFormat::BinaryBigEndian {
+            bytes.reverse();
+        }
+        writer.write_all(&bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(number_of_vertices: usize) -> PlyMesh {
+        let positions = (0..number_of_vertices)
+            .map(|index| {
+                let angle = index as f32 / number_of_vertices as f32 * 2.0 * std::f32::consts::PI;
+                Coord3d::new(angle.cos(), angle.sin(), 0.0)
+            })
+            .collect::<Vec<_>>();
+        PlyMesh {
+            colors: Some(vec![Coord3d::new(1.0, 0.0, 0.5); number_of_vertices]),
+            vertex_properties: vec![(String::from("quality"), vec![0.25; number_of_vertices])],
+            faces: vec![(0..number_of_vertices as u32).collect()],
+            positions,
+            normals: None,
+        }
+    }
+
+    #[test]
+    fn faces_with_many_vertices_round_trip() {
+        for &format in [
+            PlyFormat::Ascii,
+            PlyFormat::BinaryLittleEndian,
+            PlyFormat::BinaryBigEndian,
+        ]
+        .iter()
+        {
+            for &number_of_vertices in [3, 255, 256, 300].iter() {
+                let mesh = polygon(number_of_vertices);
+                let mut data = Vec::new();
+                mesh.write(&mut data, format).unwrap();
+                let parsed = PlyMesh::read(&data[..]).unwrap();
+
+                assert_eq!(parsed.faces, mesh.faces);
+                assert_eq!(parsed.positions, mesh.positions);
+                assert_eq!(parsed.vertex_properties, mesh.vertex_properties);
+                let colors = parsed.colors.unwrap();
+                assert!(colors.iter().all(|color| color.x == 1.0 && color.y == 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn mismatched_vertex_properties_are_rejected() {
+        let mut mesh = polygon(4);
+        mesh.vertex_properties[0].1.pop();
+
+        let err = mesh.write(Vec::new(), PlyFormat::Ascii).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
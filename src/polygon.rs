@@ -0,0 +1,448 @@
+use crate::geometry::Geometry;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use cgmath::Vector2;
+use glium::index::PrimitiveType;
+use std::error::Error;
+use std::fmt;
+
+type Coord2dF64 = Vector2<f64>;
+
+#[derive(Debug)]
+pub enum PolygonError {
+    /// The ring has less than three vertices or encloses no area. Ring 0 is the outline, ring
+    /// `i + 1` is hole `i`.
+    DegenerateRing { ring: usize },
+    /// The triangulation got stuck, which happens if rings intersect themselves or each other or
+    /// if holes lie outside of the outline.
+    NotSimple,
+}
+
+impl fmt::Display for PolygonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolygonError::DegenerateRing { ring: 0 } => write!(f, "The outline encloses no area"),
+            PolygonError::DegenerateRing { ring } => {
+                write!(f, "Hole {} encloses no area", ring - 1)
+            }
+            PolygonError::NotSimple => write!(f, "The polygon is not simple"),
+        }
+    }
+}
+
+impl Error for PolygonError {}
+
+/// A simple polygon with holes in the xy plane. The rings may have any orientation and must not
+/// repeat their first vertex at the end.
+#[derive(Clone, Debug)]
+pub struct Polygon {
+    pub outline: Vec<Coord2d>,
+    pub holes: Vec<Vec<Coord2d>>,
+}
+
+impl Polygon {
+    pub fn new(outline: Vec<Coord2d>) -> Polygon {
+        Polygon {
+            outline,
+            holes: Vec::new(),
+        }
+    }
+
+    /// Splits the polygon into counterclockwise triangles by ear clipping. Holes are first joined
+    /// to the outline by bridge edges, so that a single ring remains.
+    pub fn triangulate(&self) -> Result<Vec<[Coord2d; 3]>, PolygonError> {
+        triangulate_rings(&self.oriented_rings()?)
+    }
+
+    /// Returns the triangulated polygon at `z = 0` facing `+z`.
+    pub fn fill(&self) -> Result<Geometry, PolygonError> {
+        let mut geometry = Geometry::new(PrimitiveType::TrianglesList);
+        for [a, b, c] in self.triangulate()? {
+            geometry.push_triangle(a.extend(0.0), b.extend(0.0), c.extend(0.0));
+        }
+        Ok(geometry)
+    }
+
+    /// Returns the closed prism between `z = 0` and `z = height` with caps facing `-z` and `+z` and
+    /// flat side walls facing outward. Use `Geometry::smooth_normals` to round curved walls.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `height` is not positive.
+    pub fn extrude(&self, height: f32) -> Result<Geometry, PolygonError> {
+        assert!(height > 0.0, "The extrusion height must be positive");
+        let rings = self.oriented_rings()?;
+        let triangles = triangulate_rings(&rings)?;
+
+        let mut geometry = Geometry::new(PrimitiveType::TrianglesList);
+        for [a, b, c] in triangles {
+            geometry.push_triangle(a.extend(height), b.extend(height), c.extend(height));
+            geometry.push_triangle(c.extend(0.0), b.extend(0.0), a.extend(0.0));
+        }
+        // The outline is counterclockwise and holes are clockwise, so the solid always lies to the
+        // left of an edge.
+        for ring in &rings {
+            for (index, &from) in ring.iter().enumerate() {
+                let to = ring[(index + 1) % ring.len()];
+                geometry.push_triangle(from.extend(0.0), to.extend(0.0), to.extend(height));
+                geometry.push_triangle(from.extend(0.0), to.extend(height), from.extend(height));
+            }
+        }
+        Ok(geometry)
+    }
+
+    /// Returns the outline counterclockwise and the holes clockwise.
+    fn oriented_rings(&self) -> Result<Vec<Vec<Coord2d>>, PolygonError> {
+        let mut rings = Vec::new();
+        for (ring, points) in std::iter::once(&self.outline)
+            .chain(&self.holes)
+            .enumerate()
+        {
+            let area = signed_area(points);
+            if points.len() < 3 || area == 0.0 || !area.is_finite() {
+                return Err(PolygonError::DegenerateRing { ring });
+            }
+            let mut points = points.clone();
+            if (area > 0.0) != (ring == 0) {
+                points.reverse();
+            }
+            rings.push(points);
+        }
+        Ok(rings)
+    }
+}
+
+/// Triangulates rings as returned by `Polygon::oriented_rings`.
+fn triangulate_rings(rings: &[Vec<Coord2d>]) -> Result<Vec<[Coord2d; 3]>, PolygonError> {
+    let points = rings.iter().flatten().cloned().collect::<Vec<_>>();
+    let points_f64 = points
+        .iter()
+        .map(|point| point.cast::<f64>().unwrap())
+        .collect::<Vec<_>>();
+
+    let mut first_index = 0;
+    let mut ring_indices = Vec::new();
+    for ring in rings {
+        ring_indices.push((first_index..first_index + ring.len()).collect::<Vec<_>>());
+        first_index += ring.len();
+    }
+
+    let mut outline = ring_indices.remove(0);
+    // Holes reaching farther to the right are bridged first, so that their bridges cannot cross
+    // the ones of holes further to the left.
+    ring_indices.sort_by(|a, b| {
+        max_x(&points_f64, b)
+            .partial_cmp(&max_x(&points_f64, a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    for hole in &ring_indices {
+        bridge_hole(&points_f64, &mut outline, hole)?;
+    }
+
+    Ok(clip_ears(&points_f64, outline)?
+        .iter()
+        .map(|&[a, b, c]| [points[a], points[b], points[c]])
+        .collect())
+}
+
+fn signed_area(points: &[Coord2d]) -> f64 {
+    let points = points
+        .iter()
+        .map(|point| point.cast::<f64>().unwrap())
+        .collect::<Vec<_>>();
+    (0..points.len())
+        .map(|index| points[index].perp_dot(points[(index + 1) % points.len()]))
+        .sum::<f64>()
+        / 2.0
+}
+
+fn max_x(points: &[Coord2dF64], ring: &[usize]) -> f64 {
+    ring.iter()
+        .map(|&index| points[index].x)
+        .fold(f64::NEG_INFINITY, f64::max)
+}
+
+/// Connects `hole` to `outline` with a pair of opposite edges from its rightmost vertex `m` to a
+/// vertex `p` of the outline visible from `m` (David Eberly, "Triangulation by Ear Clipping").
+fn bridge_hole(
+    points: &[Coord2dF64],
+    outline: &mut Vec<usize>,
+    hole: &[usize],
+) -> Result<(), PolygonError> {
+    let hole_start = (0..hole.len())
+        .max_by(|&a, &b| {
+            points[hole[a]]
+                .x
+                .partial_cmp(&points[hole[b]].x)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .unwrap();
+    let m = points[hole[hole_start]];
+
+    // Cast a ray from `m` to the right and find the closest outline edge it hits.
+    let mut closest: Option<(f64, usize)> = None;
+    for position in 0..outline.len() {
+        let a = points[outline[position]];
+        let b = points[outline[(position + 1) % outline.len()]];
+        if (a.y > m.y && b.y > m.y) || (a.y < m.y && b.y < m.y) || a.y == b.y {
+            continue;
+        }
+        let x = a.x + (m.y - a.y) / (b.y - a.y) * (b.x - a.x);
+        if x >= m.x && closest.is_none_or(|(closest_x, _)| x < closest_x) {
+            closest = Some((x, position));
+        }
+    }
+    let (hit_x, edge) = closest.ok_or(PolygonError::NotSimple)?;
+    let hit = Coord2dF64::new(hit_x, m.y);
+    let edge_end = (edge + 1) % outline.len();
+    let (start, end) = (points[outline[edge]], points[outline[edge_end]]);
+    let mut bridge = if start == hit || (end != hit && start.x > end.x) {
+        edge
+    } else {
+        edge_end
+    };
+
+    // Unless the ray hits `p` itself, reflex vertices inside the triangle `m`, `hit`, `p` may hide
+    // `p`. The one enclosing the smallest angle with the ray is visible instead.
+    let p = points[outline[bridge]];
+    if p != hit {
+        let to_p = p - m;
+        let mut best_cos = to_p.x / to_p.magnitude();
+        for position in 0..outline.len() {
+            let vertex = points[outline[position]];
+            if outline[position] == outline[bridge]
+                || !is_reflex(points, outline, position)
+                || !is_in_triangle(vertex, m, hit, p)
+            {
+                continue;
+            }
+            let to_vertex = vertex - m;
+            let cos = to_vertex.x / to_vertex.magnitude();
+            let bridge_distance = (points[outline[bridge]] - m).magnitude2();
+            if cos > best_cos || (cos == best_cos && to_vertex.magnitude2() < bridge_distance) {
+                best_cos = cos;
+                bridge = position;
+            }
+        }
+    }
+
+    // Earlier bridges duplicate vertices. Attach to the copy whose interior angle contains `m`.
+    let bridge = (0..outline.len())
+        .filter(|&position| outline[position] == outline[bridge])
+        .find(|&position| is_in_sector(points, outline, position, m))
+        .unwrap_or(bridge);
+
+    let mut spliced = outline[..=bridge].to_vec();
+    spliced.extend((0..=hole.len()).map(|offset| hole[(hole_start + offset) % hole.len()]));
+    spliced.extend_from_slice(&outline[bridge..]);
+    *outline = spliced;
+    Ok(())
+}
+
+/// Clips convex corners not containing other vertices until only a triangle is left.
+fn clip_ears(points: &[Coord2dF64], mut ring: Vec<usize>) -> Result<Vec<[usize; 3]>, PolygonError> {
+    let mut triangles = Vec::new();
+    let mut position = 0;
+    let mut attempts = 0;
+    while ring.len() > 3 {
+        if attempts == ring.len() {
+            // No ear is left. Collinear or doubled back vertices can be dropped without losing any
+            // area. Otherwise, the polygon is not simple.
+            let collinear = (0..ring.len())
+                .find(|&position| corner_cross(points, &ring, position) == 0.0)
+                .ok_or(PolygonError::NotSimple)?;
+            ring.remove(collinear);
+            attempts = 0;
+            continue;
+        }
+
+        position %= ring.len();
+        if is_ear(points, &ring, position) {
+            let (previous, next) = neighbors(&ring, position);
+            triangles.push([ring[previous], ring[position], ring[next]]);
+            ring.remove(position);
+            attempts = 0;
+        } else {
+            position += 1;
+            attempts += 1;
+        }
+    }
+    // A collinear or clockwise remainder means that the clipped ears overlap the polygon.
+    if corner_cross(points, &ring, 1) <= 0.0 {
+        return Err(PolygonError::NotSimple);
+    }
+    triangles.push([ring[0], ring[1], ring[2]]);
+    Ok(triangles)
+}
+
+fn is_ear(points: &[Coord2dF64], ring: &[usize], position: usize) -> bool {
+    if corner_cross(points, ring, position) <= 0.0 {
+        return false;
+    }
+    let (previous, next) = neighbors(ring, position);
+    let corners = [ring[previous], ring[position], ring[next]];
+    let [a, b, c] = [points[corners[0]], points[corners[1]], points[corners[2]]];
+    ring.iter()
+        .filter(|index| !corners.contains(index))
+        .all(|&index| !is_in_triangle(points[index], a, b, c))
+}
+
+fn is_reflex(points: &[Coord2dF64], ring: &[usize], position: usize) -> bool {
+    corner_cross(points, ring, position) < 0.0
+}
+
+/// Returns whether `point` lies inside the interior angle of the ring at `position`.
+fn is_in_sector(points: &[Coord2dF64], ring: &[usize], position: usize, point: Coord2dF64) -> bool {
+    let (previous, next) = neighbors(ring, position);
+    let (a, b, c) = (
+        points[ring[previous]],
+        points[ring[position]],
+        points[ring[next]],
+    );
+    let left_of_incoming = (b - a).perp_dot(point - a) > 0.0;
+    let left_of_outgoing = (c - b).perp_dot(point - b) > 0.0;
+    if is_reflex(points, ring, position) {
+        left_of_incoming || left_of_outgoing
+    } else {
+        left_of_incoming && left_of_outgoing
+    }
+}
+
+/// Positive for convex and negative for reflex corners of a counterclockwise ring.
+fn corner_cross(points: &[Coord2dF64], ring: &[usize], position: usize) -> f64 {
+    let (previous, next) = neighbors(ring, position);
+    let (a, b, c) = (
+        points[ring[previous]],
+        points[ring[position]],
+        points[ring[next]],
+    );
+    (b - a).perp_dot(c - b)
+}
+
+fn neighbors(ring: &[usize], position: usize) -> (usize, usize) {
+    (
+        (position + ring.len() - 1) % ring.len(),
+        (position + 1) % ring.len(),
+    )
+}
+
+/// Includes the boundary and works for both orientations.
+fn is_in_triangle(point: Coord2dF64, a: Coord2dF64, b: Coord2dF64, c: Coord2dF64) -> bool {
+    let ab = (b - a).perp_dot(point - a);
+    let bc = (c - b).perp_dot(point - b);
+    let ca = (a - c).perp_dot(point - c);
+    (ab >= 0.0 && bc >= 0.0 && ca >= 0.0) || (ab <= 0.0 && bc <= 0.0 && ca <= 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation;
+
+    fn ring(points: &[(f32, f32)]) -> Vec<Coord2d> {
+        points.iter().map(|&(x, y)| Coord2d::new(x, y)).collect()
+    }
+
+    fn square(center: (f32, f32), size: f32) -> Vec<Coord2d> {
+        let (x, y) = center;
+        let half = size / 2.0;
+        ring(&[
+            (x - half, y - half),
+            (x + half, y - half),
+            (x + half, y + half),
+            (x - half, y + half),
+        ])
+    }
+
+    /// Sums the signed areas, asserting that every triangle is counterclockwise.
+    fn area(triangles: &[[Coord2d; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                let area = (b - a).perp_dot(c - a) / 2.0;
+                assert!(area > 0.0);
+                area
+            })
+            .sum()
+    }
+
+    fn polygon_with_holes() -> Polygon {
+        Polygon {
+            outline: square((0.0, 0.0), 10.0),
+            holes: vec![
+                square((-2.5, -2.5), 2.0),
+                square((2.5, -2.5), 2.0),
+                // Clockwise, to check that holes may have any orientation.
+                square((0.0, 2.5), 3.0).into_iter().rev().collect(),
+            ],
+        }
+    }
+
+    #[test]
+    fn triangles_cover_the_area_without_holes() {
+        let triangles = polygon_with_holes().triangulate().unwrap();
+        assert_eq!(area(&triangles), 100.0 - 4.0 - 4.0 - 9.0);
+        // A ring with n vertices and h holes splits into n + 2h - 2 triangles.
+        assert_eq!(triangles.len(), 16 + 2 * 3 - 2);
+    }
+
+    #[test]
+    fn clockwise_outline_gives_counterclockwise_triangles() {
+        let outline = ring(&[(0.0, 0.0), (0.0, 2.0), (1.0, 1.0), (2.0, 2.0), (2.0, 0.0)]);
+        let triangles = Polygon::new(outline).triangulate().unwrap();
+        assert_eq!(triangles.len(), 3);
+        assert_eq!(area(&triangles), 3.0);
+    }
+
+    #[test]
+    fn collinear_vertices_are_kept_in_the_outline() {
+        let outline = ring(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)]);
+        let triangles = Polygon::new(outline).triangulate().unwrap();
+        assert_eq!(area(&triangles), 4.0);
+    }
+
+    #[test]
+    fn extrusion_is_watertight() {
+        let solid = polygon_with_holes().extrude(2.0).unwrap();
+        let report = validation::validate(&solid, 1e-6);
+        assert!(report.is_ok(), "{:?}", report.issues);
+
+        let clockwise = Polygon::new(square((0.0, 0.0), 1.0).into_iter().rev().collect());
+        let report = validation::validate(&clockwise.extrude(1.0).unwrap(), 1e-6);
+        assert!(report.is_ok(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn fill_faces_up() {
+        let fill = polygon_with_holes().fill().unwrap();
+        for vertex in &fill.vertices {
+            assert_eq!(vertex.normal(), Coord3d::new(0.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn degenerate_rings_are_rejected() {
+        let line = Polygon::new(ring(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]));
+        assert!(matches!(
+            line.triangulate(),
+            Err(PolygonError::DegenerateRing { ring: 0 })
+        ));
+
+        let mut polygon = Polygon::new(square((0.0, 0.0), 4.0));
+        polygon.holes.push(ring(&[(0.0, 0.0), (1.0, 0.0)]));
+        assert!(matches!(
+            polygon.triangulate(),
+            Err(PolygonError::DegenerateRing { ring: 1 })
+        ));
+    }
+
+    #[test]
+    fn hole_outside_of_the_outline_is_rejected() {
+        let mut polygon = Polygon::new(square((0.0, 0.0), 2.0));
+        polygon.holes.push(square((5.0, 0.0), 1.0));
+        assert!(matches!(
+            polygon.triangulate(),
+            Err(PolygonError::NotSimple)
+        ));
+    }
+}
@@ -1,10 +1,60 @@
-use glium::implement_vertex;
+use cgmath::EuclideanSpace;
+use cgmath::InnerSpace;
+use cgmath::Point3;
+use cgmath::SquareMatrix;
+use cgmath::Transform;
+use glium::vertex::Attribute;
+use glium::vertex::AttributeType;
+use glium::vertex::VertexFormat;
+use glium::Vertex;
+use std::borrow::Cow;
 
 pub type Coord2d = cgmath::Vector2<f32>;
 pub type Coord3d = cgmath::Vector3<f32>;
+pub type Coord4d = cgmath::Vector4<f32>;
 pub type Trans3d = cgmath::Matrix3<f32>;
 pub type Trans4d = cgmath::Matrix4<f32>;
 
+/// Like `glium::implement_vertex!`, but measures the field offsets on a default-initialized vertex
+/// instead of the deprecated `std::mem::uninitialized`. All fields must be listed.
+macro_rules! implement_vertex {
+    ($struct_name:ident, $($field_name:ident),+) => {
+        impl Vertex for $struct_name {
+            fn build_bindings() -> VertexFormat {
+                fn attribute_type<T: Attribute>(_: &T) -> AttributeType {
+                    T::get_type()
+                }
+
+                let vertex = $struct_name {
+                    $($field_name: Default::default()),+
+                };
+                let start = &vertex as *const _ as usize;
+                Cow::Owned(vec![$((
+                    Cow::Borrowed(stringify!($field_name)),
+                    &vertex.$field_name as *const _ as usize - start,
+                    attribute_type(&vertex.$field_name),
+                    false,
+                )),+])
+            }
+        }
+    };
+}
+
+/// Common access to the vertex formats of this crate, used by algorithms that work on any format.
+pub trait MeshVertex: Vertex {
+    fn position(&self) -> Coord3d;
+
+    fn normal(&self) -> Coord3d;
+
+    /// Returns whether every attribute of `self` differs from the one of `other` by at most
+    /// `tolerance`.
+    fn is_near(&self, other: &Self, tolerance: f32) -> bool;
+
+    /// Returns the vertex with its position transformed by `transform` and its normal by
+    /// `normal_transform`, which usually is `transform::transform_normals(transform)`.
+    fn transformed(&self, transform: &Trans4d, normal_transform: &Trans3d) -> Self;
+}
+
 #[derive(Copy, Clone)]
 pub struct VertexAttribute {
     vertex_position: (f32, f32, f32),
@@ -48,4 +98,240 @@ impl TexVertexAttribute {
             tex_coord: tex_coord.into(),
         }
     }
+
+    pub fn tex_coord(&self) -> Coord2d {
+        self.tex_coord.into()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct ColorVertexAttribute {
+    vertex_position: (f32, f32, f32),
+    vertex_normal: (f32, f32, f32),
+    vertex_color: (f32, f32, f32),
+}
+
+implement_vertex!(
+    ColorVertexAttribute,
+    vertex_position,
+    vertex_normal,
+    vertex_color
+);
+
+impl ColorVertexAttribute {
+    pub fn new(position: Coord3d, normal: Coord3d, color: Coord3d) -> Self {
+        ColorVertexAttribute {
+            vertex_position: position.into(),
+            vertex_normal: normal.into(),
+            vertex_color: color.into(),
+        }
+    }
+
+    pub fn color(&self) -> Coord3d {
+        self.vertex_color.into()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct TangentVertexAttribute {
+    vertex_position: (f32, f32, f32),
+    vertex_normal: (f32, f32, f32),
+    tex_coord: (f32, f32),
+    vertex_tangent: (f32, f32, f32, f32),
+}
+
+implement_vertex!(
+    TangentVertexAttribute,
+    vertex_position,
+    vertex_normal,
+    tex_coord,
+    vertex_tangent
+);
+
+impl TangentVertexAttribute {
+    /// The `w` component of `tangent` is the sign of the bitangent, i.e.
+    /// `bitangent = tangent.w * normal.cross(tangent.xyz)`.
+    pub fn new(position: Coord3d, normal: Coord3d, tex_coord: Coord2d, tangent: Coord4d) -> Self {
+        TangentVertexAttribute {
+            vertex_position: position.into(),
+            vertex_normal: normal.into(),
+            tex_coord: tex_coord.into(),
+            vertex_tangent: tangent.into(),
+        }
+    }
+
+    pub fn tex_coord(&self) -> Coord2d {
+        self.tex_coord.into()
+    }
+
+    pub fn tangent(&self) -> Coord4d {
+        self.vertex_tangent.into()
+    }
+}
+
+impl MeshVertex for VertexAttribute {
+    fn position(&self) -> Coord3d {
+        self.vertex_position.into()
+    }
+
+    fn normal(&self) -> Coord3d {
+        self.vertex_normal.into()
+    }
+
+    fn is_near(&self, other: &Self, tolerance: f32) -> bool {
+        are_near(self.position(), other.position(), tolerance)
+            && are_near(self.normal(), other.normal(), tolerance)
+    }
+
+    fn transformed(&self, transform: &Trans4d, normal_transform: &Trans3d) -> Self {
+        VertexAttribute::new(
+            transform_position(transform, self.position()),
+            transform_normal(normal_transform, self.normal()),
+        )
+    }
+}
+
+impl MeshVertex for TexVertexAttribute {
+    fn position(&self) -> Coord3d {
+        self.vertex_position.into()
+    }
+
+    fn normal(&self) -> Coord3d {
+        self.vertex_normal.into()
+    }
+
+    fn is_near(&self, other: &Self, tolerance: f32) -> bool {
+        are_near(self.position(), other.position(), tolerance)
+            && are_near(self.normal(), other.normal(), tolerance)
+            && are_near(self.tex_coord(), other.tex_coord(), tolerance)
+    }
+
+    fn transformed(&self, transform: &Trans4d, normal_transform: &Trans3d) -> Self {
+        TexVertexAttribute::new(
+            transform_position(transform, self.position()),
+            transform_normal(normal_transform, self.normal()),
+            self.tex_coord(),
+        )
+    }
+}
+
+impl MeshVertex for ColorVertexAttribute {
+    fn position(&self) -> Coord3d {
+        self.vertex_position.into()
+    }
+
+    fn normal(&self) -> Coord3d {
+        self.vertex_normal.into()
+    }
+
+    fn is_near(&self, other: &Self, tolerance: f32) -> bool {
+        are_near(self.position(), other.position(), tolerance)
+            && are_near(self.normal(), other.normal(), tolerance)
+            && are_near(self.color(), other.color(), tolerance)
+    }
+
+    fn transformed(&self, transform: &Trans4d, normal_transform: &Trans3d) -> Self {
+        ColorVertexAttribute::new(
+            transform_position(transform, self.position()),
+            transform_normal(normal_transform, self.normal()),
+            self.color(),
+        )
+    }
+}
+
+impl MeshVertex for TangentVertexAttribute {
+    fn position(&self) -> Coord3d {
+        self.vertex_position.into()
+    }
+
+    fn normal(&self) -> Coord3d {
+        self.vertex_normal.into()
+    }
+
+    fn is_near(&self, other: &Self, tolerance: f32) -> bool {
+        are_near(self.position(), other.position(), tolerance)
+            && are_near(self.normal(), other.normal(), tolerance)
+            && are_near(self.tex_coord(), other.tex_coord(), tolerance)
+            && are_near(self.tangent(), other.tangent(), tolerance)
+    }
+
+    /// The tangent follows the surface, i.e. it is transformed like a position difference. Its
+    /// bitangent sign flips if `transform` mirrors the geometry.
+    fn transformed(&self, transform: &Trans4d, normal_transform: &Trans3d) -> Self {
+        let tangent = self.tangent();
+        let linear_part = Trans3d::from_cols(
+            transform[0].truncate(),
+            transform[1].truncate(),
+            transform[2].truncate(),
+        );
+        let direction = linear_part * tangent.truncate();
+        let direction = if direction.magnitude2() > 0.0 {
+            direction.normalize()
+        } else {
+            direction
+        };
+        let sign = tangent.w * linear_part.determinant().signum();
+        TangentVertexAttribute::new(
+            transform_position(transform, self.position()),
+            transform_normal(normal_transform, self.normal()),
+            self.tex_coord(),
+            direction.extend(sign),
+        )
+    }
+}
+
+fn are_near<V: InnerSpace<Scalar = f32>>(a: V, b: V, tolerance: f32) -> bool {
+    (a - b).magnitude2() <= tolerance * tolerance
+}
+
+fn transform_position(transform: &Trans4d, position: Coord3d) -> Coord3d {
+    transform
+        .transform_point(Point3::from_vec(position))
+        .to_vec()
+}
+
+/// Zero normals, as used for lines, stay zero instead of becoming NaN.
+fn transform_normal(normal_transform: &Trans3d, normal: Coord3d) -> Coord3d {
+    let normal = normal_transform * normal;
+    if normal.magnitude2() > 0.0 {
+        normal.normalize()
+    } else {
+        normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn vertex_bindings_cover_all_fields_without_overlap() {
+        let bindings = TangentVertexAttribute::build_bindings();
+        let mut ranges = bindings
+            .iter()
+            .map(|(_, offset, attribute_type, _)| {
+                (*offset, offset + attribute_type.get_size_bytes())
+            })
+            .collect::<Vec<_>>();
+        ranges.sort();
+        let names = bindings
+            .iter()
+            .map(|binding| &binding.0[..])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "vertex_position",
+                "vertex_normal",
+                "tex_coord",
+                "vertex_tangent"
+            ]
+        );
+        assert!(ranges.windows(2).all(|pair| pair[0].1 <= pair[1].0));
+        assert_eq!(
+            ranges.iter().map(|(start, end)| end - start).sum::<usize>(),
+            mem::size_of::<TangentVertexAttribute>()
+        );
+    }
 }
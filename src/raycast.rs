@@ -0,0 +1,514 @@
+use crate::bounds::Aabb;
+use crate::geometry::Geometry;
+use crate::prelude::*;
+use crate::transform;
+use cgmath::EuclideanSpace;
+use cgmath::InnerSpace;
+use cgmath::Point3;
+use cgmath::SquareMatrix;
+use cgmath::Transform;
+use glium::index::PrimitiveType;
+
+/// Maximum number of triangles in a leaf of the hierarchy.
+const MAX_LEAF_SIZE: usize = 4;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: Coord3d,
+    /// Need not be normalized. Distances along the ray are measured in multiples of its length.
+    pub direction: Coord3d,
+}
+
+impl Ray {
+    pub fn new(origin: Coord3d, direction: Coord3d) -> Ray {
+        Ray { origin, direction }
+    }
+
+    pub fn at(&self, distance: f32) -> Coord3d {
+        self.origin + distance * self.direction
+    }
+
+    /// Transforms the ray by an affine transform. The direction is not renormalized, so that
+    /// distances along the transformed ray identify the same points as before.
+    pub fn transformed(&self, transform: &Trans4d) -> Ray {
+        Ray {
+            origin: transform
+                .transform_point(Point3::from_vec(self.origin))
+                .to_vec(),
+            direction: transform.transform_vector(self.direction),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Hit {
+    /// The ray parameter of the hit, see `Ray::at`.
+    pub distance: f32,
+    pub point: Coord3d,
+    /// The weights of the three triangle corners at `point`.
+    pub barycentric: Coord3d,
+    /// The index of the triangle in the triangle list.
+    pub triangle: usize,
+    /// The interpolated vertex normal or, if the vertex normals are unusable, the face normal.
+    pub normal: Coord3d,
+}
+
+/// A bounding volume hierarchy over the triangles of a geometry.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    triangles: Vec<Triangle>,
+}
+
+struct Triangle {
+    index: usize,
+    corners: [Coord3d; 3],
+    normals: [Coord3d; 3],
+}
+
+struct Node {
+    bounds: Aabb,
+    content: NodeContent,
+}
+
+enum NodeContent {
+    Leaf {
+        first: usize,
+        count: usize,
+    },
+    /// The left child directly follows its parent.
+    Branch {
+        right: usize,
+    },
+}
+
+impl Bvh {
+    /// # Panics
+    ///
+    /// Panics if `geometry` is not a `TrianglesList`.
+    pub fn new<V: MeshVertex>(geometry: &Geometry<V>) -> Bvh {
+        assert_eq!(
+            geometry.primitive_type,
+            PrimitiveType::TrianglesList,
+            "Ray casting requires a triangle list"
+        );
+        let mut triangles = geometry
+            .vertices
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(index, vertices)| Triangle {
+                index,
+                corners: [
+                    vertices[0].position(),
+                    vertices[1].position(),
+                    vertices[2].position(),
+                ],
+                normals: [
+                    vertices[0].normal(),
+                    vertices[1].normal(),
+                    vertices[2].normal(),
+                ],
+            })
+            .collect::<Vec<_>>();
+
+        let mut nodes = Vec::new();
+        if !triangles.is_empty() {
+            build(&mut nodes, &mut triangles, 0);
+        }
+        Bvh { nodes, triangles }
+    }
+
+    /// Returns `None` if the geometry has no triangles.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.bounds)
+    }
+
+    /// Returns the hit closest to the ray origin with a distance between `0` and `max_distance`.
+    /// Triangles are hit from both sides.
+    pub fn closest_hit(&self, ray: &Ray, max_distance: f32) -> Option<Hit> {
+        let mut closest = None;
+        self.traverse(ray, max_distance, |hit| {
+            closest = Some(hit);
+            false
+        });
+        closest
+    }
+
+    /// Returns any hit with a distance between `0` and `max_distance`, which is faster than
+    /// `closest_hit` if only the existence of a hit matters, e.g. for shadow rays.
+    pub fn any_hit(&self, ray: &Ray, max_distance: f32) -> Option<Hit> {
+        let mut any = None;
+        self.traverse(ray, max_distance, |hit| {
+            any = Some(hit);
+            true
+        });
+        any
+    }
+
+    /// Visits the nodes intersecting the ray, nearer children first. Every hit closer than all
+    /// previous ones is passed to `on_hit`, which returns whether to stop.
+    fn traverse<F: FnMut(Hit) -> bool>(&self, ray: &Ray, max_distance: f32, mut on_hit: F) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inverse_direction = Coord3d::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+        let mut max_distance = max_distance;
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if entry_distance(&node.bounds, ray, inverse_direction, max_distance).is_none() {
+                continue;
+            }
+            match node.content {
+                NodeContent::Leaf { first, count } => {
+                    for triangle in &self.triangles[first..first + count] {
+                        if let Some(hit) = intersect(ray, triangle, max_distance) {
+                            max_distance = hit.distance;
+                            if on_hit(hit) {
+                                return;
+                            }
+                        }
+                    }
+                }
+                NodeContent::Branch { right } => {
+                    let left = node_index + 1;
+                    let entry = |child: usize| {
+                        entry_distance(
+                            &self.nodes[child].bounds,
+                            ray,
+                            inverse_direction,
+                            max_distance,
+                        )
+                    };
+                    match (entry(left), entry(right)) {
+                        (Some(left_entry), Some(right_entry)) if right_entry < left_entry => {
+                            stack.push(left);
+                            stack.push(right);
+                        }
+                        (Some(_), Some(_)) => {
+                            stack.push(right);
+                            stack.push(left);
+                        }
+                        (Some(_), None) => stack.push(left),
+                        (None, Some(_)) => stack.push(right),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Finds the object hit first by `ray`. Each object is given by the hierarchy over its geometry
+/// and its object transform. Returns the index of the object and the hit, whose point and normal
+/// are transformed into the space of `ray`. Objects with a singular transform are ignored.
+pub fn pick<'a, I>(ray: &Ray, objects: I) -> Option<(usize, Hit)>
+where
+    I: IntoIterator<Item = (&'a Bvh, &'a Trans4d)>,
+{
+    let mut closest: Option<(usize, Hit)> = None;
+    for (index, (bvh, object_transform)) in objects.into_iter().enumerate() {
+        let inverse = match object_transform.invert() {
+            Some(inverse) => inverse,
+            None => continue,
+        };
+        let max_distance = closest
+            .map(|(_, hit)| hit.distance)
+            .unwrap_or(f32::INFINITY);
+        if let Some(hit) = bvh.closest_hit(&ray.transformed(&inverse), max_distance) {
+            let normal = transform::transform_normals(object_transform) * hit.normal;
+            closest = Some((
+                index,
+                Hit {
+                    point: ray.at(hit.distance),
+                    normal: normal.normalize(),
+                    ..hit
+                },
+            ));
+        }
+    }
+    closest
+}
+
+/// Appends the subtree over `triangles`, whose first triangle has the index `first`, and returns
+/// the index of its root.
+fn build(nodes: &mut Vec<Node>, triangles: &mut [Triangle], first: usize) -> usize {
+    let bounds = Aabb::from_points(
+        triangles
+            .iter()
+            .flat_map(|triangle| triangle.corners.iter().cloned()),
+    )
+    .unwrap();
+    let node_index = nodes.len();
+
+    if triangles.len() <= MAX_LEAF_SIZE {
+        nodes.push(Node {
+            bounds,
+            content: NodeContent::Leaf {
+                first,
+                count: triangles.len(),
+            },
+        });
+        return node_index;
+    }
+
+    // Split at the median centroid along the axis in which the centroids spread the most.
+    let centroid = |triangle: &Triangle| {
+        (triangle.corners[0] + triangle.corners[1] + triangle.corners[2]) / 3.0
+    };
+    let centroid_bounds = Aabb::from_points(triangles.iter().map(centroid)).unwrap();
+    let size = centroid_bounds.size();
+    let axis = if size.x >= size.y && size.x >= size.z {
+        0
+    } else if size.y >= size.z {
+        1
+    } else {
+        2
+    };
+    let middle = triangles.len() / 2;
+    triangles.select_nth_unstable_by(middle, |a, b| {
+        centroid(a)[axis]
+            .partial_cmp(&centroid(b)[axis])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    nodes.push(Node {
+        bounds,
+        content: NodeContent::Branch { right: 0 },
+    });
+    let (left_triangles, right_triangles) = triangles.split_at_mut(middle);
+    build(nodes, left_triangles, first);
+    let right = build(nodes, right_triangles, first + middle);
+    nodes[node_index].content = NodeContent::Branch { right };
+    node_index
+}
+
+/// The slab test. Returns the distance at which the ray enters `bounds` if it does so before
+/// `max_distance`.
+fn entry_distance(
+    bounds: &Aabb,
+    ray: &Ray,
+    inverse_direction: Coord3d,
+    max_distance: f32,
+) -> Option<f32> {
+    let mut near = 0.0f32;
+    let mut far = max_distance;
+    for axis in 0..3 {
+        let t1 = (bounds.min[axis] - ray.origin[axis]) * inverse_direction[axis];
+        let t2 = (bounds.max[axis] - ray.origin[axis]) * inverse_direction[axis];
+        // `min` and `max` ignore the NaN produced by rays parallel to a slab touching the ray.
+        near = near.max(t1.min(t2));
+        far = far.min(t1.max(t2));
+    }
+    if near <= far {
+        Some(near)
+    } else {
+        None
+    }
+}
+
+/// The Möller-Trumbore ray-triangle intersection.
+fn intersect(ray: &Ray, triangle: &Triangle, max_distance: f32) -> Option<Hit> {
+    let [v1, v2, v3] = triangle.corners;
+    let edge1 = v2 - v1;
+    let edge2 = v3 - v1;
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant == 0.0 || !determinant.is_finite() {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let to_origin = ray.origin - v1;
+    let u = to_origin.dot(p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = to_origin.cross(edge1);
+    let v = ray.direction.dot(q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = edge2.dot(q) * inverse_determinant;
+    if !(0.0..=max_distance).contains(&distance) {
+        return None;
+    }
+
+    let barycentric = Coord3d::new(1.0 - u - v, u, v);
+    let [n1, n2, n3] = triangle.normals;
+    let interpolated = barycentric.x * n1 + barycentric.y * n2 + barycentric.z * n3;
+    let normal = if interpolated.magnitude2() > 0.0 && interpolated.magnitude2().is_finite() {
+        interpolated.normalize()
+    } else {
+        (v3 - v1).cross(v3 - v2).normalize()
+    };
+
+    Some(Hit {
+        distance,
+        point: ray.at(distance),
+        barycentric,
+        triangle: triangle.index,
+        normal,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solids;
+    use cgmath::Matrix4;
+
+    /// Intersects the ray with every triangle, without the hierarchy.
+    fn brute_force_hits<V: MeshVertex>(
+        geometry: &Geometry<V>,
+        ray: &Ray,
+        max_distance: f32,
+    ) -> Vec<Hit> {
+        geometry
+            .vertices
+            .chunks_exact(3)
+            .enumerate()
+            .filter_map(|(index, vertices)| {
+                let triangle = Triangle {
+                    index,
+                    corners: [
+                        vertices[0].position(),
+                        vertices[1].position(),
+                        vertices[2].position(),
+                    ],
+                    normals: [
+                        vertices[0].normal(),
+                        vertices[1].normal(),
+                        vertices[2].normal(),
+                    ],
+                };
+                intersect(ray, &triangle, max_distance)
+            })
+            .collect()
+    }
+
+    fn brute_force_closest(hits: &[Hit]) -> Option<f32> {
+        hits.iter()
+            .map(|hit| hit.distance)
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+    }
+
+    /// Deterministic rays from outside of the unit sphere towards points around it.
+    fn rays(count: usize) -> Vec<Ray> {
+        let mut state = 12345u32;
+        let mut random = move || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+        };
+        (0..count)
+            .map(|_| {
+                let origin = Coord3d::new(random(), random(), random()).normalize() * 3.0;
+                let target = Coord3d::new(random(), random(), random()) * 1.2;
+                Ray::new(origin, target - origin)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hierarchy_agrees_with_brute_force() {
+        let bounds = Aabb {
+            min: Coord3d::new(-1.2, -1.2, -1.2),
+            max: Coord3d::new(1.2, 1.2, 1.2),
+        };
+        let distance = |position: Coord3d| position.magnitude() - 1.0;
+        let sphere = Geometry::from_isosurface(&distance, bounds, [12, 12, 12], 0.0);
+        let bvh = Bvh::new(&sphere);
+        let mut hits = 0;
+        for ray in rays(200) {
+            for &max_distance in [f32::INFINITY, 0.6].iter() {
+                let expected = brute_force_hits(&sphere, &ray, max_distance);
+                let closest = bvh.closest_hit(&ray, max_distance);
+                assert_eq!(
+                    closest.map(|hit| hit.distance),
+                    brute_force_closest(&expected)
+                );
+                let any = bvh.any_hit(&ray, max_distance);
+                assert_eq!(any.is_some(), !expected.is_empty());
+                if let Some(any) = any {
+                    assert!(any.distance <= max_distance);
+                    hits += 1;
+                }
+            }
+        }
+        // Make sure both hits and misses were tested.
+        assert!(hits > 50 && hits < 350);
+    }
+
+    #[test]
+    fn ray_past_the_geometry_misses() {
+        let bvh = Bvh::new(&solids::cube());
+        let ray = Ray::new(Coord3d::new(0.0, 3.0, 5.0), Coord3d::new(0.0, 0.0, -1.0));
+        assert!(bvh.closest_hit(&ray, f32::INFINITY).is_none());
+        assert!(bvh.any_hit(&ray, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn ray_through_a_shared_edge_hits() {
+        let cube = solids::cube();
+        let bvh = Bvh::new(&cube);
+        // The diagonal of the face at z = 1 runs through its center.
+        let ray = Ray::new(Coord3d::new(0.0, 0.0, 5.0), Coord3d::new(0.0, 0.0, -1.0));
+        let hit = bvh.closest_hit(&ray, f32::INFINITY).unwrap();
+        assert_eq!(hit.distance, 4.0);
+        assert_eq!(hit.point, Coord3d::new(0.0, 0.0, 1.0));
+        assert_eq!(hit.normal, Coord3d::new(0.0, 0.0, 1.0));
+        assert_eq!(
+            Some(hit.distance),
+            brute_force_closest(&brute_force_hits(&cube, &ray, f32::INFINITY))
+        );
+    }
+
+    #[test]
+    fn rays_parallel_to_faces() {
+        let bvh = Bvh::new(&solids::cube());
+        // Parallel to the faces at z = +-1 and outside of the cube.
+        let above = Ray::new(Coord3d::new(-5.0, 0.0, 2.0), Coord3d::new(1.0, 0.0, 0.0));
+        assert!(bvh.closest_hit(&above, f32::INFINITY).is_none());
+
+        // Parallel to the faces at z = +-1, but through the faces at x = +-1.
+        let through = Ray::new(Coord3d::new(-5.0, 0.0, 0.5), Coord3d::new(1.0, 0.0, 0.0));
+        let hit = bvh.closest_hit(&through, f32::INFINITY).unwrap();
+        assert_eq!(hit.distance, 4.0);
+        assert_eq!(hit.normal, Coord3d::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn max_distance_cuts_off_hits() {
+        let bvh = Bvh::new(&solids::cube());
+        let ray = Ray::new(Coord3d::new(0.0, 0.5, 5.0), Coord3d::new(0.0, 0.0, -2.0));
+        assert!(bvh.closest_hit(&ray, 1.9).is_none());
+        assert!(bvh.any_hit(&ray, 1.9).is_none());
+        assert_eq!(bvh.closest_hit(&ray, 2.1).unwrap().distance, 2.0);
+        assert!(bvh.any_hit(&ray, 2.1).is_some());
+        assert_eq!(bvh.closest_hit(&ray, f32::INFINITY).unwrap().distance, 2.0);
+    }
+
+    #[test]
+    fn pick_finds_the_closest_transformed_instance() {
+        let bvh = Bvh::new(&solids::cube());
+        let near =
+            Matrix4::from_translation(Coord3d::new(0.0, 0.0, -10.0)) * Matrix4::from_scale(2.0);
+        let far = Matrix4::from_translation(Coord3d::new(0.0, 0.0, 10.0));
+        let singular = Matrix4::from_scale(0.0);
+        let objects = [(&bvh, &far), (&bvh, &singular), (&bvh, &near)];
+
+        let ray = Ray::new(Coord3d::new(0.0, 0.5, -20.0), Coord3d::new(0.0, 0.0, 1.0));
+        let (index, hit) = pick(&ray, objects.iter().cloned()).unwrap();
+        assert_eq!(index, 2);
+        assert!((hit.distance - 8.0).abs() < 1e-5);
+        assert!((hit.point - Coord3d::new(0.0, 0.5, -12.0)).magnitude() < 1e-5);
+        assert!((hit.normal - Coord3d::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+
+        let away = Ray::new(Coord3d::new(0.0, 0.5, -20.0), Coord3d::new(0.0, 0.0, -1.0));
+        assert!(pick(&away, objects.iter().cloned()).is_none());
+    }
+}
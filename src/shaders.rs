@@ -6,13 +6,16 @@ use glium::uniforms::UniformsStorage;
 
 type Cons<'a, Next, T> = UniformsStorage<'a, T, Next>;
 type Nil = EmptyUniforms;
+type TransformAndLight<'a> =
+    Cons<'a, Cons<'a, Cons<'a, Nil, [[f32; 4]; 4]>, [[f32; 3]; 3]>, [f32; 3]>;
+type TransformLightAndTangents<'a> = Cons<'a, TransformAndLight<'a>, [[f32; 3]; 3]>;
 
 pub fn create_uniforms<'a>(
     total_transform: Trans4d,
     normal_transform: Trans3d,
     light_direction: Coord3d,
     color: Coord3d,
-) -> Cons<'a, Cons<'a, Cons<'a, Cons<'a, Nil, [[f32; 4]; 4]>, [[f32; 3]; 3]>, [f32; 3]>, [f32; 3]> {
+) -> Cons<'a, TransformAndLight<'a>, [f32; 3]> {
     uniform! {
         position_transform: total_transform.into(),
         normal_transform: normal_transform.into(),
@@ -21,17 +24,50 @@ pub fn create_uniforms<'a>(
     }
 }
 
+/// Uniforms for `color_vertex.glsl`, which takes the color from the vertex attributes.
+pub fn create_color_uniforms<'a>(
+    total_transform: Trans4d,
+    normal_transform: Trans3d,
+    light_direction: Coord3d,
+) -> TransformAndLight<'a> {
+    uniform! {
+        position_transform: total_transform.into(),
+        normal_transform: normal_transform.into(),
+        light_direction: light_direction.into(),
+    }
+}
+
 pub fn create_tex_uniforms<'a>(
     total_transform: Trans4d,
     normal_transform: Trans3d,
     light_direction: Coord3d,
-    texture: &Texture2d,
-) -> Cons<'a, Cons<'a, Cons<'a, Cons<'a, Nil, [[f32; 4]; 4]>, [[f32; 3]; 3]>, [f32; 3]>, &Texture2d>
-{
+    texture: &'a Texture2d,
+) -> Cons<'a, TransformAndLight<'a>, &'a Texture2d> {
+    uniform! {
+        position_transform: total_transform.into(),
+        normal_transform: normal_transform.into(),
+        light_direction: light_direction.into(),
+        tex: texture,
+    }
+}
+
+/// Uniforms for `normal_map_vertex.glsl`, which expects a `TangentVertexAttribute`.
+/// `tangent_transform` is usually `transform::transform_tangents(object_transform)`. The normal
+/// map stores tangent space normals with components mapped from `-1..1` to `0..1`.
+pub fn create_normal_map_uniforms<'a>(
+    total_transform: Trans4d,
+    normal_transform: Trans3d,
+    tangent_transform: Trans3d,
+    light_direction: Coord3d,
+    texture: &'a Texture2d,
+    normal_map: &'a Texture2d,
+) -> Cons<'a, Cons<'a, TransformLightAndTangents<'a>, &'a Texture2d>, &'a Texture2d> {
     uniform! {
         position_transform: total_transform.into(),
         normal_transform: normal_transform.into(),
         light_direction: light_direction.into(),
-        color: texture,
+        tangent_transform: tangent_transform.into(),
+        tex: texture,
+        normal_map: normal_map,
     }
 }
@@ -0,0 +1,557 @@
+use crate::geometry::weld_positions;
+use crate::geometry::Geometry;
+use crate::geometry::NormalWeighting;
+use crate::geometry::POSITION_TOLERANCE;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use cgmath::Rad;
+use cgmath::SquareMatrix;
+use cgmath::Vector3;
+use glium::index::PrimitiveType;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+
+/// Weight of the planes that keep boundary vertices on the boundary, relative to face planes.
+const BOUNDARY_WEIGHT: f64 = 1000.0;
+
+/// A collapse is rejected if it turns the normal of a triangle by more than about 80 degrees.
+const MIN_NORMAL_COS: f64 = 0.2;
+
+type Coord3dF64 = Vector3<f64>;
+
+#[derive(Copy, Clone, Debug)]
+pub struct SimplifyOptions {
+    /// Collapsing stops as soon as at most this many triangles remain.
+    pub target_triangles: usize,
+    /// Collapsing also stops before a collapse whose error, the sum of squared distances of the
+    /// new vertex to the planes of the original triangles around it, exceeds this value.
+    pub max_error: f32,
+    /// The normals of the result are smoothed across edges flatter than this angle.
+    pub crease_angle: Rad<f32>,
+}
+
+/// Reduces the number of triangles by repeatedly collapsing the edge whose replacement vertex
+/// deviates least from the original surface, as measured by the quadric error metric of
+/// Garland and Heckbert. Boundaries are preserved by additional planes perpendicular to them and
+/// collapses that would flip triangles or pinch the surface are skipped.
+///
+/// Only the positions of `geometry` are taken into account. Corners at the same position are
+/// merged regardless of their other attributes, and the result has flat or smoothed normals
+/// according to `crease_angle` but no texture coordinates, colors or tangents.
+///
+/// # Panics
+///
+/// Panics if `geometry` is not a `TrianglesList`.
+pub fn simplify<V: MeshVertex>(geometry: &Geometry<V>, options: &SimplifyOptions) -> Geometry {
+    assert_eq!(
+        geometry.primitive_type,
+        PrimitiveType::TrianglesList,
+        "Only triangle lists can be simplified"
+    );
+
+    let (positions, indices) = weld_positions(
+        geometry.vertices.iter().map(MeshVertex::position),
+        POSITION_TOLERANCE,
+    );
+    let triangles = indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .filter(|&[a, b, c]| a != b && b != c && c != a)
+        .collect();
+
+    let mut mesh = CollapsibleMesh::new(positions, triangles);
+    mesh.collapse_edges(options.target_triangles, f64::from(options.max_error));
+
+    let mut simplified = Geometry::new(PrimitiveType::TrianglesList);
+    for triangle in mesh.living_triangles() {
+        let [a, b, c] = triangle.map(|vertex| mesh.position(vertex));
+        simplified.push_triangle(a, b, c);
+    }
+    simplified.smooth_normals(options.crease_angle, NormalWeighting::Angle);
+    simplified
+}
+
+/// A symmetric 4x4 matrix measuring the sum of squared distances to a set of planes.
+#[derive(Copy, Clone)]
+struct Quadric {
+    a: [[f64; 3]; 3],
+    b: Coord3dF64,
+    c: f64,
+}
+
+impl Quadric {
+    fn zero() -> Quadric {
+        Quadric {
+            a: [[0.0; 3]; 3],
+            b: Coord3dF64::new(0.0, 0.0, 0.0),
+            c: 0.0,
+        }
+    }
+
+    /// The quadric of the plane through `point` with the unit normal `normal`.
+    fn from_plane(normal: Coord3dF64, point: Coord3dF64, weight: f64) -> Quadric {
+        let d = -normal.dot(point);
+        let mut a = [[0.0; 3]; 3];
+        for (row, a_row) in a.iter_mut().enumerate() {
+            for (column, entry) in a_row.iter_mut().enumerate() {
+                *entry = weight * normal[row] * normal[column];
+            }
+        }
+        Quadric {
+            a,
+            b: weight * d * normal,
+            c: weight * d * d,
+        }
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut a = self.a;
+        for (row, a_row) in a.iter_mut().enumerate() {
+            for (column, entry) in a_row.iter_mut().enumerate() {
+                *entry += other.a[row][column];
+            }
+        }
+        Quadric {
+            a,
+            b: self.b + other.b,
+            c: self.c + other.c,
+        }
+    }
+
+    fn error(&self, point: Coord3dF64) -> f64 {
+        let a_point = Coord3dF64::new(
+            Coord3dF64::from(self.a[0]).dot(point),
+            Coord3dF64::from(self.a[1]).dot(point),
+            Coord3dF64::from(self.a[2]).dot(point),
+        );
+        (point.dot(a_point) + 2.0 * self.b.dot(point) + self.c).max(0.0)
+    }
+
+    /// The point minimizing the error or `None` if the minimum is not unique.
+    fn minimum(&self) -> Option<Coord3dF64> {
+        let matrix = cgmath::Matrix3::from(self.a);
+        if matrix.determinant().abs() < 1e-12 {
+            return None;
+        }
+        matrix.invert().map(|inverse| -(inverse * self.b))
+    }
+}
+
+struct Candidate {
+    error: f64,
+    position: Coord3dF64,
+    vertices: [u32; 2],
+    versions: [u32; 2],
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Candidate) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    /// Reversed, so that `BinaryHeap` yields the candidate with the smallest error first.
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        other
+            .error
+            .partial_cmp(&self.error)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.vertices.cmp(&self.vertices))
+    }
+}
+
+struct CollapsibleMesh {
+    positions: Vec<Coord3dF64>,
+    quadrics: Vec<Quadric>,
+    /// Incremented whenever a vertex moves, invalidating the candidates it belongs to.
+    versions: Vec<u32>,
+    triangles: Vec<[u32; 3]>,
+    is_alive: Vec<bool>,
+    vertex_triangles: Vec<Vec<usize>>,
+    is_boundary: Vec<bool>,
+    number_of_living_triangles: usize,
+}
+
+impl CollapsibleMesh {
+    fn new(positions: Vec<Coord3d>, triangles: Vec<[u32; 3]>) -> CollapsibleMesh {
+        let positions = positions
+            .into_iter()
+            .map(|position| position.cast::<f64>().unwrap())
+            .collect::<Vec<_>>();
+        let mut vertex_triangles = vec![Vec::new(); positions.len()];
+        let mut edge_count = HashMap::<_, usize>::new();
+        for (index, triangle) in triangles.iter().enumerate() {
+            for (i, &vertex) in triangle.iter().enumerate() {
+                vertex_triangles[vertex as usize].push(index);
+                *edge_count
+                    .entry(edge_key(vertex, triangle[(i + 1) % 3]))
+                    .or_default() += 1;
+            }
+        }
+
+        let mut mesh = CollapsibleMesh {
+            quadrics: vec![Quadric::zero(); positions.len()],
+            versions: vec![0; positions.len()],
+            is_alive: vec![true; triangles.len()],
+            is_boundary: vec![false; positions.len()],
+            number_of_living_triangles: triangles.len(),
+            positions,
+            triangles,
+            vertex_triangles,
+        };
+
+        for index in 0..mesh.triangles.len() {
+            let triangle = mesh.triangles[index];
+            let normal = match mesh.normal(triangle) {
+                Some(normal) => normal,
+                None => continue,
+            };
+            let face_quadric =
+                Quadric::from_plane(normal, mesh.positions[triangle[0] as usize], 1.0);
+            for (i, &vertex) in triangle.iter().enumerate() {
+                let next = triangle[(i + 1) % 3];
+                mesh.quadrics[vertex as usize] = mesh.quadrics[vertex as usize].add(&face_quadric);
+
+                if edge_count[&edge_key(vertex, next)] == 1 {
+                    let from = mesh.positions[vertex as usize];
+                    let to = mesh.positions[next as usize];
+                    let edge_normal = (to - from).cross(normal);
+                    if edge_normal.magnitude2() > 0.0 {
+                        let boundary_quadric =
+                            Quadric::from_plane(edge_normal.normalize(), from, BOUNDARY_WEIGHT);
+                        for &end in [vertex, next].iter() {
+                            mesh.is_boundary[end as usize] = true;
+                            mesh.quadrics[end as usize] =
+                                mesh.quadrics[end as usize].add(&boundary_quadric);
+                        }
+                    }
+                }
+            }
+        }
+
+        mesh
+    }
+
+    fn collapse_edges(&mut self, target_triangles: usize, max_error: f64) {
+        let mut candidates = BinaryHeap::new();
+        for vertex in 0..self.positions.len() as u32 {
+            for neighbor in self.neighbors(vertex) {
+                if vertex < neighbor {
+                    candidates.push(self.candidate(vertex, neighbor));
+                }
+            }
+        }
+
+        while self.number_of_living_triangles > target_triangles {
+            let candidate = match candidates.pop() {
+                Some(candidate) => candidate,
+                None => break,
+            };
+            let [a, b] = candidate.vertices;
+            if candidate.versions != [self.versions[a as usize], self.versions[b as usize]] {
+                continue;
+            }
+            if candidate.error > max_error {
+                break;
+            }
+            if !self.can_collapse(a, b, candidate.position) {
+                continue;
+            }
+
+            self.collapse(a, b, candidate.position);
+            for neighbor in self.neighbors(a) {
+                candidates.push(self.candidate(a, neighbor));
+            }
+        }
+    }
+
+    fn candidate(&self, a: u32, b: u32) -> Candidate {
+        let quadric = self.quadrics[a as usize].add(&self.quadrics[b as usize]);
+        let (position_a, position_b) = (self.positions[a as usize], self.positions[b as usize]);
+        let mut options = vec![position_a, position_b, (position_a + position_b) / 2.0];
+        options.extend(quadric.minimum());
+        let (error, position) = options
+            .into_iter()
+            .map(|position| (quadric.error(position), position))
+            .min_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(Ordering::Equal))
+            .unwrap();
+        Candidate {
+            error,
+            position,
+            vertices: [a, b],
+            versions: [self.versions[a as usize], self.versions[b as usize]],
+        }
+    }
+
+    /// Checks that collapsing the edge keeps the surface manifold and does not flip triangles.
+    fn can_collapse(&self, a: u32, b: u32, position: Coord3dF64) -> bool {
+        let shared_triangles = self
+            .living_triangles_of(a)
+            .filter(|&index| self.triangles[index].contains(&b))
+            .count();
+        let neighbors_a = self.neighbors(a);
+        let shared_neighbors = self
+            .neighbors(b)
+            .into_iter()
+            .filter(|neighbor| neighbors_a.contains(neighbor))
+            .count();
+        // The link condition: the only vertices adjacent to both ends are the tips of the
+        // triangles sharing the edge.
+        if shared_neighbors != shared_triangles {
+            return false;
+        }
+        let is_boundary_edge = shared_triangles == 1;
+        if self.is_boundary[a as usize] && self.is_boundary[b as usize] && !is_boundary_edge {
+            return false;
+        }
+
+        for &vertex in [a, b].iter() {
+            for index in self.living_triangles_of(vertex) {
+                let triangle = self.triangles[index];
+                if triangle.contains(&a) && triangle.contains(&b) {
+                    continue;
+                }
+                let moved = triangle.map(|corner| if corner == vertex { a } else { corner });
+                let old_normal = match self.normal(triangle) {
+                    Some(normal) => normal,
+                    None => continue,
+                };
+                let new_normal = self.normal_with(moved, a, position);
+                match new_normal {
+                    Some(new_normal) if new_normal.dot(old_normal) >= MIN_NORMAL_COS => {}
+                    _ => return false,
+                }
+            }
+        }
+        true
+    }
+
+    fn collapse(&mut self, a: u32, b: u32, position: Coord3dF64) {
+        for index in self.vertex_triangles[b as usize].clone() {
+            if !self.is_alive[index] {
+                continue;
+            }
+            if self.triangles[index].contains(&a) {
+                self.is_alive[index] = false;
+                self.number_of_living_triangles -= 1;
+            } else {
+                for corner in self.triangles[index].iter_mut() {
+                    if *corner == b {
+                        *corner = a;
+                    }
+                }
+                self.vertex_triangles[a as usize].push(index);
+            }
+        }
+        self.vertex_triangles[b as usize].clear();
+        let living_triangles = self.living_triangles_of(a).collect();
+        self.vertex_triangles[a as usize] = living_triangles;
+
+        self.positions[a as usize] = position;
+        self.quadrics[a as usize] = self.quadrics[a as usize].add(&self.quadrics[b as usize]);
+        self.is_boundary[a as usize] |= self.is_boundary[b as usize];
+        self.versions[a as usize] += 1;
+        self.versions[b as usize] += 1;
+    }
+
+    fn living_triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.triangles
+            .iter()
+            .zip(&self.is_alive)
+            .filter(|&(_, &is_alive)| is_alive)
+            .map(|(&triangle, _)| triangle)
+    }
+
+    fn living_triangles_of(&self, vertex: u32) -> impl Iterator<Item = usize> + '_ {
+        self.vertex_triangles[vertex as usize]
+            .iter()
+            .cloned()
+            .filter(move |&index| self.is_alive[index])
+    }
+
+    fn neighbors(&self, vertex: u32) -> Vec<u32> {
+        let mut neighbors = self
+            .living_triangles_of(vertex)
+            .flat_map(|index| self.triangles[index].to_vec())
+            .filter(|&neighbor| neighbor != vertex)
+            .collect::<Vec<_>>();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+
+    fn position(&self, vertex: u32) -> Coord3d {
+        self.positions[vertex as usize].cast::<f32>().unwrap()
+    }
+
+    fn normal(&self, triangle: [u32; 3]) -> Option<Coord3dF64> {
+        self.normal_with(triangle, triangle[0], self.positions[triangle[0] as usize])
+    }
+
+    /// The normal of `triangle` with `moved_vertex` placed at `position`.
+    fn normal_with(
+        &self,
+        triangle: [u32; 3],
+        moved_vertex: u32,
+        position: Coord3dF64,
+    ) -> Option<Coord3dF64> {
+        let [v1, v2, v3] = triangle.map(|vertex| {
+            if vertex == moved_vertex {
+                position
+            } else {
+                self.positions[vertex as usize]
+            }
+        });
+        let normal = (v3 - v1).cross(v3 - v2);
+        if normal.magnitude2() > 0.0 {
+            Some(normal.normalize())
+        } else {
+            None
+        }
+    }
+}
+
+fn edge_key(a: u32, b: u32) -> (u32, u32) {
+    (a.min(b), a.max(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounds::Aabb;
+    use crate::geometry::face_normal_and_area;
+    use crate::validation;
+
+    fn options(target_triangles: usize) -> SimplifyOptions {
+        SimplifyOptions {
+            target_triangles,
+            max_error: f32::INFINITY,
+            crease_angle: Rad(0.5),
+        }
+    }
+
+    /// Two triangles per cell of a 16 by 16 grid over the unit square.
+    fn height_field(height: fn(f32, f32) -> f32) -> Geometry {
+        let point = |x: usize, y: usize| {
+            let (x, y) = (x as f32 / 16.0, y as f32 / 16.0);
+            Coord3d::new(x, y, height(x, y))
+        };
+        let mut geometry = Geometry::new(PrimitiveType::TrianglesList);
+        for y in 0..16 {
+            for x in 0..16 {
+                geometry.push_triangle(point(x, y), point(x + 1, y), point(x + 1, y + 1));
+                geometry.push_triangle(point(x, y), point(x + 1, y + 1), point(x, y + 1));
+            }
+        }
+        geometry
+    }
+
+    /// A closed unit sphere without collapsed triangles.
+    fn sphere() -> Geometry {
+        let bounds = Aabb {
+            min: Coord3d::new(-1.2, -1.2, -1.2),
+            max: Coord3d::new(1.2, 1.2, 1.2),
+        };
+        let distance = |position: Coord3d| position.magnitude() - 1.0;
+        Geometry::from_isosurface(&distance, bounds, [20, 20, 20], 0.0)
+    }
+
+    fn face_normals(geometry: &Geometry) -> Vec<Coord3d> {
+        geometry
+            .vertices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                face_normal_and_area(
+                    triangle[0].position(),
+                    triangle[1].position(),
+                    triangle[2].position(),
+                )
+                .map(|(normal, _)| normal)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reaches_target_on_closed_surface() {
+        let sphere = sphere();
+
+        let simplified = simplify(&sphere, &options(200));
+        let triangles = simplified.vertices.len() / 3;
+        assert!((190..=200).contains(&triangles), "{}", triangles);
+        let report = validation::validate(&simplified, POSITION_TOLERANCE);
+        assert!(report.is_ok(), "{}", report);
+        for vertex in &simplified.vertices {
+            assert!((vertex.position().magnitude() - 1.0).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn max_error_stops_collapsing() {
+        let sphere = sphere();
+        let options = SimplifyOptions {
+            max_error: 0.0,
+            ..options(0)
+        };
+
+        let simplified = simplify(&sphere, &options);
+        assert_eq!(simplified.vertices.len(), sphere.vertices.len());
+    }
+
+    #[test]
+    fn preserves_boundary_of_flat_patch() {
+        let patch = height_field(|_, _| 0.0);
+        let options = SimplifyOptions {
+            max_error: 1e-9,
+            ..options(0)
+        };
+
+        let simplified = simplify(&patch, &options);
+        let area = simplified
+            .vertices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                face_normal_and_area(
+                    triangle[0].position(),
+                    triangle[1].position(),
+                    triangle[2].position(),
+                )
+                .map(|(_, area)| area)
+            })
+            .sum::<f32>();
+        assert!((area - 1.0).abs() < 1e-4, "{}", area);
+        assert!(simplified.vertices.len() / 3 < 20);
+        for vertex in &simplified.vertices {
+            let position = vertex.position();
+            let on_boundary =
+                |coordinate: f32| coordinate.abs() < 1e-5 || (coordinate - 1.0).abs() < 1e-5;
+            assert!(on_boundary(position.x) || on_boundary(position.y));
+        }
+    }
+
+    #[test]
+    fn does_not_flip_triangles() {
+        let bumps = height_field(|x, y| 0.2 * (6.0 * x).sin() * (5.0 * y).cos());
+        let reference = face_normals(&bumps)[0].z.signum();
+        assert!(face_normals(&bumps)
+            .iter()
+            .all(|normal| normal.z * reference > 0.0));
+
+        for &target in [400, 100, 20].iter() {
+            let simplified = simplify(&bumps, &options(target));
+            assert!(simplified.vertices.len() / 3 <= target);
+            for normal in face_normals(&simplified) {
+                assert!(normal.z * reference > 0.0, "{:?}", normal);
+            }
+        }
+    }
+}
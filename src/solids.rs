@@ -1,4 +1,5 @@
 use crate::geometry::Geometry;
+use crate::half_edge::HalfEdgeMesh;
 use crate::prelude::Coord3d;
 use glium::index::PrimitiveType;
 
@@ -47,3 +48,26 @@ pub fn cube() -> Geometry {
 
     cube
 }
+
+/// The same cube as `cube` with quads instead of triangles, e.g. for Catmull-Clark subdivision.
+pub fn cube_mesh() -> HalfEdgeMesh {
+    let mut positions = Vec::new();
+    for &x in [-1.0, 1.0].iter() {
+        for &y in [-1.0, 1.0].iter() {
+            for &z in [-1.0, 1.0].iter() {
+                positions.push(Coord3d::new(x, y, z));
+            }
+        }
+    }
+
+    // The index of a corner is 4x + 2y + z with coordinates 0 or 1.
+    let faces = [
+        vec![7, 3, 1, 5],
+        vec![6, 4, 0, 2],
+        vec![7, 5, 4, 6],
+        vec![3, 2, 0, 1],
+        vec![7, 6, 2, 3],
+        vec![5, 1, 0, 4],
+    ];
+    HalfEdgeMesh::from_polygons(positions, &faces).unwrap()
+}
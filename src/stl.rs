@@ -0,0 +1,447 @@
+use crate::geometry::Geometry;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use glium::index::PrimitiveType;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+#[derive(Debug)]
+pub enum StlError {
+    Io(io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+    Truncated {
+        expected_size: usize,
+        actual_size: usize,
+    },
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StlError::Io(err) => write!(f, "Could not read STL data: {}", err),
+            StlError::Parse { line, message } => write!(f, "Line {}: {}", line, message),
+            StlError::Truncated {
+                expected_size,
+                actual_size,
+            } => write!(
+                f,
+                "Binary STL data has {} bytes, expected {}",
+                actual_size, expected_size
+            ),
+        }
+    }
+}
+
+impl Error for StlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StlError::Io(err) => Some(err),
+            StlError::Parse { .. } | StlError::Truncated { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for StlError {
+    fn from(err: io::Error) -> Self {
+        StlError::Io(err)
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Geometry, StlError> {
+    read(File::open(path)?)
+}
+
+/// Reads ASCII or binary STL data. Binary data is recognized by its size matching the triangle
+/// count in its header, since binary headers may also start with `solid`.
+pub fn read<R: Read>(mut reader: R) -> Result<Geometry, StlError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let is_binary = data.len() >= HEADER_SIZE + 4
+        && HEADER_SIZE + 4 + TRIANGLE_SIZE * triangle_count(&data) == data.len();
+    let looks_like_ascii = data
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .map(|start| data[start..].starts_with(b"solid"))
+        .unwrap_or(false);
+
+    if !is_binary && looks_like_ascii {
+        read_ascii(&data[..])
+    } else {
+        read_binary(&data[..])
+    }
+}
+
+/// Reads binary STL data. Facets with a zero normal receive the normal computed by
+/// `Geometry::push_triangle`.
+pub fn read_binary<R: Read>(mut reader: R) -> Result<Geometry, StlError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    if data.len() < HEADER_SIZE + 4 {
+        return Err(StlError::Truncated {
+            expected_size: HEADER_SIZE + 4,
+            actual_size: data.len(),
+        });
+    }
+    let number_of_triangles = triangle_count(&data);
+    let expected_size = HEADER_SIZE + 4 + TRIANGLE_SIZE * number_of_triangles;
+    if data.len() < expected_size {
+        return Err(StlError::Truncated {
+            expected_size,
+            actual_size: data.len(),
+        });
+    }
+
+    let mut geometry = Geometry::new(PrimitiveType::TrianglesList);
+    for triangle in data[HEADER_SIZE + 4..expected_size].chunks(TRIANGLE_SIZE) {
+        let coord = |index: usize| {
+            let component = |offset: usize| {
+                let start = 12 * index + 4 * offset;
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&triangle[start..start + 4]);
+                f32::from_le_bytes(bytes)
+            };
+            Coord3d::new(component(0), component(1), component(2))
+        };
+        push_facet(&mut geometry, coord(0), [coord(1), coord(2), coord(3)]);
+    }
+    Ok(geometry)
+}
+
+/// Reads ASCII STL data, possibly containing several solids. Facets with a zero normal receive the
+/// normal computed by `Geometry::push_triangle`.
+pub fn read_ascii<R: BufRead>(reader: R) -> Result<Geometry, StlError> {
+    let mut geometry = Geometry::new(PrimitiveType::TrianglesList);
+    let mut normal = None;
+    let mut vertices = Vec::new();
+    let mut number_of_lines = 0;
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        number_of_lines = line_number + 1;
+        let parse_error = |message: String| StlError::Parse {
+            line: line_number + 1,
+            message,
+        };
+
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("facet") => {
+                if tokens.next() != Some("normal") {
+                    return Err(parse_error(String::from("Expected 'facet normal'")));
+                }
+                normal = Some(parse_coord(tokens).map_err(parse_error)?);
+                vertices.clear();
+            }
+            Some("vertex") => {
+                if normal.is_none() {
+                    return Err(parse_error(String::from("Vertex outside of facet")));
+                }
+                vertices.push(parse_coord(tokens).map_err(parse_error)?);
+            }
+            Some("endfacet") => {
+                let facet_normal = normal
+                    .take()
+                    .ok_or_else(|| parse_error(String::from("'endfacet' without 'facet'")))?;
+                if vertices.len() != 3 {
+                    return Err(parse_error(format!(
+                        "Facet has {} vertices, expected 3",
+                        vertices.len()
+                    )));
+                }
+                push_facet(
+                    &mut geometry,
+                    facet_normal,
+                    [vertices[0], vertices[1], vertices[2]],
+                );
+            }
+            Some("solid") | Some("endsolid") | Some("outer") | Some("endloop") | None => {}
+            Some(keyword) => {
+                return Err(parse_error(format!("Unexpected keyword '{}'", keyword)));
+            }
+        }
+    }
+
+    if normal.is_some() {
+        return Err(StlError::Parse {
+            line: number_of_lines,
+            message: String::from("Unexpected end of data inside of a facet"),
+        });
+    }
+    Ok(geometry)
+}
+
+pub fn save_ascii<P: AsRef<Path>>(path: P, geometry: &Geometry, name: &str) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_ascii(&mut writer, geometry, name)?;
+    writer.flush()
+}
+
+/// Writes `geometry` as ASCII STL. The facet normals are the averaged vertex normals of each
+/// triangle.
+///
+/// # Panics
+///
+/// Panics if `geometry` is not a `TrianglesList`.
+pub fn write_ascii<W: Write>(mut writer: W, geometry: &Geometry, name: &str) -> io::Result<()> {
+    writeln!(writer, "solid {}", name)?;
+    for triangle in triangles(geometry) {
+        let normal = facet_normal(triangle);
+        writeln!(
+            writer,
+            "  facet normal {:e} {:e} {:e}",
+            normal.x, normal.y, normal.z
+        )?;
+        writeln!(writer, "    outer loop")?;
+        for vertex in triangle {
+            let position = vertex.position();
+            writeln!(
+                writer,
+                "      vertex {:e} {:e} {:e}",
+                position.x, position.y, position.z
+            )?;
+        }
+        writeln!(writer, "    endloop")?;
+        writeln!(writer, "  endfacet")?;
+    }
+    writeln!(writer, "endsolid {}", name)
+}
+
+pub fn save_binary<P: AsRef<Path>>(path: P, geometry: &Geometry) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_binary(&mut writer, geometry)?;
+    writer.flush()
+}
+
+/// Writes `geometry` as binary STL. The facet normals are the averaged vertex normals of each
+/// triangle.
+///
+/// Fails with `io::ErrorKind::InvalidInput` if there are more triangles than the 32-bit count in
+/// the header can represent.
+///
+/// # Panics
+///
+/// Panics if `geometry` is not a `TrianglesList`.
+pub fn write_binary<W: Write>(mut writer: W, geometry: &Geometry) -> io::Result<()> {
+    let triangles = triangles(geometry);
+    let number_of_triangles = u32::try_from(geometry.vertices.len() / 3).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Binary STL cannot store more than 2^32 - 1 triangles",
+        )
+    })?;
+
+    let mut header = [b' '; HEADER_SIZE];
+    header[..7].copy_from_slice(b"cg-util");
+    writer.write_all(&header)?;
+    writer.write_all(&number_of_triangles.to_le_bytes())?;
+
+    for triangle in triangles {
+        let coords = Some(facet_normal(triangle))
+            .into_iter()
+            .chain(triangle.iter().map(VertexAttribute::position));
+        for coord in coords {
+            for &component in [coord.x, coord.y, coord.z].iter() {
+                writer.write_all(&component.to_le_bytes())?;
+            }
+        }
+        writer.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+fn triangle_count(data: &[u8]) -> usize {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[HEADER_SIZE..HEADER_SIZE + 4]);
+    u32::from_le_bytes(bytes) as usize
+}
+
+fn parse_coord<'a, I: Iterator<Item = &'a str>>(tokens: I) -> Result<Coord3d, String> {
+    let components = tokens
+        .map(|token| {
+            token
+                .parse()
+                .map_err(|_| format!("Invalid number '{}'", token))
+        })
+        .collect::<Result<Vec<f32>, _>>()?;
+    match components[..] {
+        [x, y, z] => Ok(Coord3d::new(x, y, z)),
+        _ => Err(format!(
+            "Expected 3 coordinates, found {}",
+            components.len()
+        )),
+    }
+}
+
+fn push_facet(geometry: &mut Geometry, normal: Coord3d, vertices: [Coord3d; 3]) {
+    let length = normal.magnitude();
+    if length > 0.0 && length.is_finite() {
+        for &vertex in vertices.iter() {
+            geometry
+                .vertices
+                .push(VertexAttribute::new(vertex, normal / length));
+        }
+    } else {
+        geometry.push_triangle(vertices[0], vertices[1], vertices[2]);
+    }
+}
+
+fn triangles(geometry: &Geometry) -> impl Iterator<Item = &[VertexAttribute]> {
+    assert_eq!(
+        geometry.primitive_type,
+        PrimitiveType::TrianglesList,
+        "Only triangle lists can be written as STL"
+    );
+    geometry.vertices.chunks_exact(3)
+}
+
+fn facet_normal(triangle: &[VertexAttribute]) -> Coord3d {
+    let normal_sum = triangle
+        .iter()
+        .map(VertexAttribute::normal)
+        .fold(Coord3d::new(0.0, 0.0, 0.0), |sum, normal| sum + normal);
+    let length = normal_sum.magnitude();
+    if length > 0.0 && length.is_finite() {
+        normal_sum / length
+    } else {
+        Coord3d::new(0.0, 0.0, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solids;
+
+    fn assert_same_triangles(read: &Geometry, written: &Geometry) {
+        assert_eq!(read.vertices.len(), written.vertices.len());
+        for (read, written) in read.vertices.iter().zip(&written.vertices) {
+            assert_eq!(read.position(), written.position());
+            assert!((read.normal() - written.normal()).magnitude() < 1e-6);
+        }
+    }
+
+    fn binary_data(header: &[u8], triangles: &[[f32; 12]]) -> Vec<u8> {
+        let mut data = header.to_vec();
+        data.resize(HEADER_SIZE, b' ');
+        data.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            for component in triangle {
+                data.extend_from_slice(&component.to_le_bytes());
+            }
+            data.extend_from_slice(&[0, 0]);
+        }
+        data
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let cube = solids::cube();
+        let mut data = Vec::new();
+        write_ascii(&mut data, &cube, "cube").unwrap();
+        assert!(data.starts_with(b"solid cube"));
+        assert_same_triangles(&read(&data[..]).unwrap(), &cube);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let cube = solids::cube();
+        let mut data = Vec::new();
+        write_binary(&mut data, &cube).unwrap();
+        assert_eq!(data.len(), HEADER_SIZE + 4 + 12 * TRIANGLE_SIZE);
+        assert_same_triangles(&read(&data[..]).unwrap(), &cube);
+    }
+
+    #[test]
+    fn binary_header_may_start_with_solid() {
+        let triangle = [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let data = binary_data(b"solid exported by some tool", &[triangle]);
+        let geometry = read(&data[..]).unwrap();
+        assert_eq!(geometry.vertices.len(), 3);
+        assert_eq!(geometry.vertices[2].position(), Coord3d::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn short_binary_body_is_truncated() {
+        let triangle = [0.0; 12];
+        let mut data = binary_data(b"binary", &[triangle, triangle]);
+        data.truncate(data.len() - 10);
+        match read_binary(&data[..]) {
+            Err(StlError::Truncated {
+                expected_size,
+                actual_size,
+            }) => {
+                assert_eq!(expected_size, HEADER_SIZE + 4 + 2 * TRIANGLE_SIZE);
+                assert_eq!(actual_size, expected_size - 10);
+            }
+            _ => panic!("Expected truncated data"),
+        }
+    }
+
+    #[test]
+    fn facet_needs_three_vertices() {
+        let data = "solid bad
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+    endloop
+  endfacet
+endsolid bad
+";
+        match read(data.as_bytes()) {
+            Err(StlError::Parse { line: 7, .. }) => {}
+            result => panic!("Expected a parse error in line 7, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn data_must_not_end_inside_of_a_facet() {
+        let data = "solid bad
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+";
+        match read(data.as_bytes()) {
+            Err(StlError::Parse { line: 4, .. }) => {}
+            result => panic!("Expected a parse error in line 4, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn zero_normals_are_computed() {
+        let data = "solid flat
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid flat
+";
+        let ascii = read(data.as_bytes()).unwrap();
+        let triangle = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let binary = read(&binary_data(b"binary", &[triangle])[..]).unwrap();
+        for geometry in [ascii, binary].iter() {
+            for vertex in &geometry.vertices {
+                assert_eq!(vertex.normal(), Coord3d::new(0.0, 0.0, 1.0));
+            }
+        }
+    }
+}
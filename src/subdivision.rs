@@ -0,0 +1,501 @@
+use crate::geometry::Geometry;
+use crate::geometry::IndexedGeometry;
+use crate::half_edge::HalfEdgeError;
+use crate::half_edge::HalfEdgeMesh;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use cgmath::Rad;
+use std::collections::HashSet;
+use std::f32::consts::PI;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BoundaryRule {
+    /// Boundaries are refined into smooth B-spline curves, like creases.
+    Smooth,
+    /// Boundary vertices keep their positions, so that open surfaces keep their outline.
+    Fixed,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SubdivisionOptions {
+    pub levels: usize,
+    pub boundary_rule: BoundaryRule,
+    /// Edges of the input whose adjacent faces meet at a larger angle stay sharp. `None` smooths
+    /// all interior edges.
+    pub crease_angle: Option<Rad<f32>>,
+}
+
+impl Default for SubdivisionOptions {
+    fn default() -> Self {
+        SubdivisionOptions {
+            levels: 1,
+            boundary_rule: BoundaryRule::Smooth,
+            crease_angle: None,
+        }
+    }
+}
+
+/// Refines a triangle mesh with Loop's scheme. Each level splits every triangle into four.
+///
+/// # Panics
+///
+/// Panics if `mesh` contains faces that are not triangles.
+pub fn loop_subdivision(mesh: &HalfEdgeMesh, options: &SubdivisionOptions) -> HalfEdgeMesh {
+    assert!(
+        (0..mesh.number_of_faces()).all(|face| mesh.face_half_edges(face).count() == 3),
+        "Loop subdivision requires a triangle mesh"
+    );
+    subdivide(mesh, options, loop_level)
+}
+
+/// Refines a polygon mesh with the Catmull-Clark scheme. Each level splits every `n`-gon into `n`
+/// quads. The scheme is designed for quad meshes such as `solids::cube_mesh`, but accepts any
+/// polygons.
+pub fn catmull_clark(mesh: &HalfEdgeMesh, options: &SubdivisionOptions) -> HalfEdgeMesh {
+    subdivide(mesh, options, catmull_clark_level)
+}
+
+/// Applies `loop_subdivision` to a triangle list whose corners at most `tolerance` apart are
+/// merged, see `HalfEdgeMesh::from_geometry`. The result shares its vertices between faces and
+/// carries smooth normals, see `HalfEdgeMesh::to_indexed`. Attributes other than the position
+/// are not carried over.
+///
+/// # Panics
+///
+/// Panics if `geometry` is not a `TrianglesList`.
+pub fn loop_subdivision_geometry<V: MeshVertex>(
+    geometry: &Geometry<V>,
+    tolerance: f32,
+    options: &SubdivisionOptions,
+) -> Result<IndexedGeometry, HalfEdgeError> {
+    let mesh = HalfEdgeMesh::from_geometry(geometry, tolerance)?;
+    Ok(loop_subdivision(&mesh, options).to_indexed())
+}
+
+/// Like `loop_subdivision_geometry` but refines with `catmull_clark`. The resulting quads are
+/// split into two triangles each.
+///
+/// # Panics
+///
+/// Panics if `geometry` is not a `TrianglesList`.
+pub fn catmull_clark_geometry<V: MeshVertex>(
+    geometry: &Geometry<V>,
+    tolerance: f32,
+    options: &SubdivisionOptions,
+) -> Result<IndexedGeometry, HalfEdgeError> {
+    let mesh = HalfEdgeMesh::from_geometry(geometry, tolerance)?;
+    Ok(catmull_clark(&mesh, options).to_indexed())
+}
+
+type Level = fn(&HalfEdgeMesh, &Topology) -> (Vec<Coord3d>, Vec<Vec<u32>>);
+
+fn subdivide(mesh: &HalfEdgeMesh, options: &SubdivisionOptions, level: Level) -> HalfEdgeMesh {
+    let mut creases = HashSet::new();
+    if let Some(crease_angle) = options.crease_angle {
+        let min_cos = crease_angle.0.cos();
+        for half_edge in 0..mesh.number_of_half_edges() {
+            let twin = mesh.twin(half_edge);
+            if let (Some(face), Some(other_face)) = (mesh.face(half_edge), mesh.face(twin)) {
+                if mesh.face_normal(face).dot(mesh.face_normal(other_face)) < min_cos {
+                    creases.insert(edge_key(mesh.origin(half_edge), mesh.target(half_edge)));
+                }
+            }
+        }
+    }
+
+    let mut current = None;
+    for _ in 0..options.levels {
+        let mesh = current.as_ref().unwrap_or(mesh);
+        let topology = Topology::new(mesh, &creases, options.boundary_rule);
+        let (positions, faces) = level(mesh, &topology);
+
+        // Every crease edge is split at its edge point into two crease edges.
+        creases = creases
+            .iter()
+            .flat_map(|&(a, b)| {
+                let edge_point = topology.edge_point_index(mesh, a, b);
+                vec![edge_key(a, edge_point), edge_key(edge_point, b)]
+            })
+            .collect();
+        current = Some(
+            HalfEdgeMesh::from_polygons(positions, &faces)
+                .expect("Subdivision should preserve manifold connectivity"),
+        );
+    }
+
+    current.unwrap_or_else(|| mesh.clone())
+}
+
+/// Edge numbering and sharpness information shared by both schemes.
+struct Topology {
+    /// The edge of every half-edge. Edge points are numbered after the vertices.
+    edges: Vec<usize>,
+    /// One half-edge per edge.
+    edge_half_edges: Vec<usize>,
+    is_sharp_edge: Vec<bool>,
+    vertex_rules: Vec<VertexRule>,
+}
+
+#[derive(Copy, Clone)]
+enum VertexRule {
+    Smooth,
+    /// The vertex lies on exactly two sharp edges and moves along the curve they form.
+    Crease(usize, usize),
+    /// The vertex keeps its position. Also used for vertices without edges, which have no
+    /// neighbors to average.
+    Corner,
+}
+
+impl Topology {
+    fn new(
+        mesh: &HalfEdgeMesh,
+        creases: &HashSet<(usize, usize)>,
+        boundary_rule: BoundaryRule,
+    ) -> Topology {
+        let mut edges = vec![usize::MAX; mesh.number_of_half_edges()];
+        let mut edge_half_edges = Vec::new();
+        let mut is_sharp_edge = Vec::new();
+        for half_edge in 0..mesh.number_of_half_edges() {
+            if edges[half_edge] == usize::MAX {
+                let twin = mesh.twin(half_edge);
+                edges[half_edge] = edge_half_edges.len();
+                edges[twin] = edge_half_edges.len();
+                edge_half_edges.push(half_edge);
+                let is_boundary = mesh.face(half_edge).is_none() || mesh.face(twin).is_none();
+                let is_crease =
+                    creases.contains(&edge_key(mesh.origin(half_edge), mesh.target(half_edge)));
+                is_sharp_edge.push(is_boundary || is_crease);
+            }
+        }
+
+        let vertex_rules = (0..mesh.number_of_vertices())
+            .map(|vertex| {
+                let sharp_neighbors = mesh
+                    .vertex_half_edges(vertex)
+                    .filter(|&half_edge| is_sharp_edge[edges[half_edge]])
+                    .map(|half_edge| mesh.target(half_edge))
+                    .collect::<Vec<_>>();
+                let is_fixed =
+                    boundary_rule == BoundaryRule::Fixed && mesh.is_boundary_vertex(vertex);
+                let is_isolated = mesh.vertex_half_edges(vertex).next().is_none();
+                match sharp_neighbors[..] {
+                    _ if is_fixed || is_isolated => VertexRule::Corner,
+                    [] | [_] => VertexRule::Smooth,
+                    [a, b] => VertexRule::Crease(a, b),
+                    _ => VertexRule::Corner,
+                }
+            })
+            .collect();
+
+        Topology {
+            edges,
+            edge_half_edges,
+            is_sharp_edge,
+            vertex_rules,
+        }
+    }
+
+    fn edge_point_index(&self, mesh: &HalfEdgeMesh, a: usize, b: usize) -> usize {
+        let half_edge = mesh
+            .vertex_half_edges(a)
+            .find(|&half_edge| mesh.target(half_edge) == b)
+            .unwrap();
+        mesh.number_of_vertices() + self.edges[half_edge]
+    }
+
+    /// Applies the crease and corner rules shared by both schemes or calls `smooth_rule`.
+    fn vertex_point<F: Fn() -> Coord3d>(
+        &self,
+        mesh: &HalfEdgeMesh,
+        vertex: usize,
+        smooth_rule: F,
+    ) -> Coord3d {
+        let position = mesh.positions[vertex];
+        match self.vertex_rules[vertex] {
+            VertexRule::Smooth => smooth_rule(),
+            VertexRule::Crease(a, b) => {
+                (6.0 * position + mesh.positions[a] + mesh.positions[b]) / 8.0
+            }
+            VertexRule::Corner => position,
+        }
+    }
+}
+
+fn loop_level(mesh: &HalfEdgeMesh, topology: &Topology) -> (Vec<Coord3d>, Vec<Vec<u32>>) {
+    let mut positions = (0..mesh.number_of_vertices())
+        .map(|vertex| {
+            topology.vertex_point(mesh, vertex, || {
+                let neighbors = mesh.vertex_neighbors(vertex).collect::<Vec<_>>();
+                let valence = neighbors.len() as f32;
+                let beta =
+                    (5.0 / 8.0 - (3.0 / 8.0 + (2.0 * PI / valence).cos() / 4.0).powi(2)) / valence;
+                let neighbor_sum = neighbors
+                    .iter()
+                    .fold(Coord3d::new(0.0, 0.0, 0.0), |sum, &neighbor| {
+                        sum + mesh.positions[neighbor]
+                    });
+                (1.0 - valence * beta) * mesh.positions[vertex] + beta * neighbor_sum
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for (edge, &half_edge) in topology.edge_half_edges.iter().enumerate() {
+        let a = mesh.positions[mesh.origin(half_edge)];
+        let b = mesh.positions[mesh.target(half_edge)];
+        let edge_point = if topology.is_sharp_edge[edge] {
+            (a + b) / 2.0
+        } else {
+            let c = mesh.positions[mesh.target(mesh.next(half_edge))];
+            let d = mesh.positions[mesh.target(mesh.next(mesh.twin(half_edge)))];
+            (3.0 * (a + b) + c + d) / 8.0
+        };
+        positions.push(edge_point);
+    }
+
+    let edge_point =
+        |half_edge: usize| (mesh.number_of_vertices() + topology.edges[half_edge]) as u32;
+    let mut faces = Vec::new();
+    for face in 0..mesh.number_of_faces() {
+        let half_edges = mesh.face_half_edges(face).collect::<Vec<_>>();
+        for i in 0..3 {
+            let previous = half_edges[(i + 2) % 3];
+            faces.push(vec![
+                mesh.origin(half_edges[i]) as u32,
+                edge_point(half_edges[i]),
+                edge_point(previous),
+            ]);
+        }
+        faces.push(
+            half_edges
+                .iter()
+                .map(|&half_edge| edge_point(half_edge))
+                .collect(),
+        );
+    }
+
+    (positions, faces)
+}
+
+fn catmull_clark_level(mesh: &HalfEdgeMesh, topology: &Topology) -> (Vec<Coord3d>, Vec<Vec<u32>>) {
+    let face_points = (0..mesh.number_of_faces())
+        .map(|face| {
+            average(
+                mesh.face_vertices(face)
+                    .map(|vertex| mesh.positions[vertex]),
+            )
+        })
+        .collect::<Vec<_>>();
+    let edge_midpoint = |half_edge: usize| {
+        (mesh.positions[mesh.origin(half_edge)] + mesh.positions[mesh.target(half_edge)]) / 2.0
+    };
+
+    let mut positions = (0..mesh.number_of_vertices())
+        .map(|vertex| {
+            topology.vertex_point(mesh, vertex, || {
+                let valence = mesh.vertex_half_edges(vertex).count() as f32;
+                let face_average = average(mesh.vertex_faces(vertex).map(|face| face_points[face]));
+                let edge_average = average(mesh.vertex_half_edges(vertex).map(edge_midpoint));
+                (face_average + 2.0 * edge_average + (valence - 3.0) * mesh.positions[vertex])
+                    / valence
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for (edge, &half_edge) in topology.edge_half_edges.iter().enumerate() {
+        let edge_point = if topology.is_sharp_edge[edge] {
+            edge_midpoint(half_edge)
+        } else {
+            let face = mesh.face(half_edge).unwrap();
+            let other_face = mesh.face(mesh.twin(half_edge)).unwrap();
+            (edge_midpoint(half_edge) + (face_points[face] + face_points[other_face]) / 2.0) / 2.0
+        };
+        positions.push(edge_point);
+    }
+
+    let first_face_point = positions.len();
+    positions.extend(face_points);
+
+    let edge_point =
+        |half_edge: usize| (mesh.number_of_vertices() + topology.edges[half_edge]) as u32;
+    let mut faces = Vec::new();
+    for face in 0..mesh.number_of_faces() {
+        let half_edges = mesh.face_half_edges(face).collect::<Vec<_>>();
+        for (i, &half_edge) in half_edges.iter().enumerate() {
+            let previous = half_edges[(i + half_edges.len() - 1) % half_edges.len()];
+            faces.push(vec![
+                mesh.origin(half_edge) as u32,
+                edge_point(half_edge),
+                (first_face_point + face) as u32,
+                edge_point(previous),
+            ]);
+        }
+    }
+
+    (positions, faces)
+}
+
+fn average<I: Iterator<Item = Coord3d>>(coords: I) -> Coord3d {
+    let (sum, count) = coords.fold((Coord3d::new(0.0, 0.0, 0.0), 0), |(sum, count), coord| {
+        (sum + coord, count + 1)
+    });
+    sum / count as f32
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solids;
+    use crate::validation;
+
+    fn with_levels(levels: usize) -> SubdivisionOptions {
+        SubdivisionOptions {
+            levels,
+            ..SubdivisionOptions::default()
+        }
+    }
+
+    /// The Catmull-Clark limit position of an original vertex of a closed quad mesh, given by
+    /// its position, its edge neighbors and the opposite corners of its quads.
+    fn catmull_clark_limit(mesh: &HalfEdgeMesh, vertex: usize) -> Coord3d {
+        let valence = mesh.vertex_half_edges(vertex).count() as f32;
+        let edge_sum = mesh
+            .vertex_neighbors(vertex)
+            .fold(Coord3d::new(0.0, 0.0, 0.0), |sum, neighbor| {
+                sum + mesh.positions[neighbor]
+            });
+        let face_sum = mesh
+            .vertex_half_edges(vertex)
+            .map(|half_edge| mesh.target(mesh.next(half_edge)))
+            .fold(Coord3d::new(0.0, 0.0, 0.0), |sum, opposite| {
+                sum + mesh.positions[opposite]
+            });
+        (valence * valence * mesh.positions[vertex] + 4.0 * edge_sum + face_sum)
+            / (valence * (valence + 5.0))
+    }
+
+    #[test]
+    fn catmull_clark_cube_counts() {
+        let cube = solids::cube_mesh();
+        let mut expected = (8, 6);
+        for levels in 1..4 {
+            let refined = catmull_clark(&cube, &with_levels(levels));
+            let (vertices, faces) = expected;
+            let edges = 2 * faces;
+            expected = (vertices + edges + faces, 4 * faces);
+
+            assert_eq!(refined.number_of_vertices(), expected.0);
+            assert_eq!(refined.number_of_faces(), expected.1);
+            assert!(refined.is_closed());
+        }
+    }
+
+    #[test]
+    fn catmull_clark_cube_converges_to_limit() {
+        let cube = solids::cube_mesh();
+        let first_level = catmull_clark(&cube, &with_levels(1));
+        let corner = first_level.positions[0];
+        let expected_corner = corner.map(|component| component.signum() * 5.0 / 9.0);
+        assert!((corner - expected_corner).magnitude() < 1e-6);
+
+        // The limit stencil gives the same point on every level, and the vertex approaches it.
+        let limit = catmull_clark_limit(&first_level, 0);
+        let mut previous_distance = (corner - limit).magnitude();
+        for levels in 2..6 {
+            let refined = catmull_clark(&cube, &with_levels(levels));
+            assert!((catmull_clark_limit(&refined, 0) - limit).magnitude() < 1e-5);
+            let distance = (refined.positions[0] - limit).magnitude();
+            assert!(distance < previous_distance / 2.0);
+            previous_distance = distance;
+        }
+        assert!(previous_distance < 1e-3);
+    }
+
+    #[test]
+    fn loop_tetrahedron_counts() {
+        let tetrahedron = HalfEdgeMesh::from_geometry(&solids::tetrahedron(), 1e-5).unwrap();
+        let mut expected = (4, 6, 4);
+        for levels in 1..4 {
+            let refined = loop_subdivision(&tetrahedron, &with_levels(levels));
+            let (vertices, edges, faces) = expected;
+            expected = (vertices + edges, 2 * edges + 3 * faces, 4 * faces);
+
+            assert_eq!(refined.number_of_vertices(), expected.0);
+            assert_eq!(refined.number_of_faces(), expected.2);
+            assert_eq!(refined.number_of_half_edges(), 2 * expected.1);
+            assert!(refined.is_closed());
+        }
+    }
+
+    #[test]
+    fn fixed_boundary_keeps_outline() {
+        let square = HalfEdgeMesh::from_polygons(
+            vec![
+                Coord3d::new(0.0, 0.0, 0.0),
+                Coord3d::new(1.0, 0.0, 0.0),
+                Coord3d::new(1.0, 1.0, 0.0),
+                Coord3d::new(0.0, 1.0, 0.0),
+            ],
+            &[vec![0, 1, 2], vec![0, 2, 3]],
+        )
+        .unwrap();
+        let options = SubdivisionOptions {
+            levels: 2,
+            boundary_rule: BoundaryRule::Fixed,
+            crease_angle: None,
+        };
+
+        let refined = loop_subdivision(&square, &options);
+        assert_eq!(refined.positions[..4], square.positions[..]);
+    }
+
+    #[test]
+    fn geometry_entry_points_stay_closed() {
+        let cube = solids::cube();
+        let loop_refined = loop_subdivision_geometry(&cube, 1e-5, &with_levels(2)).unwrap();
+        let catmull_clark_refined = catmull_clark_geometry(&cube, 1e-5, &with_levels(1)).unwrap();
+
+        assert_eq!(loop_refined.indices.len(), 3 * 12 * 16);
+        assert_eq!(catmull_clark_refined.vertices.len(), 8 + 18 + 12);
+        assert_eq!(catmull_clark_refined.indices.len(), 3 * 2 * 36);
+        for refined in [loop_refined, catmull_clark_refined].iter() {
+            let report = validation::validate(&refined.to_geometry(), 1e-5);
+            assert!(report.is_watertight(), "{}", report);
+            assert!(report.is_consistently_oriented(), "{}", report);
+        }
+    }
+
+    #[test]
+    fn unused_vertices_keep_their_position() {
+        let unused = Coord3d::new(5.0, 5.0, 5.0);
+        let with_unused = |mesh: &HalfEdgeMesh| {
+            let mut positions = mesh.positions.clone();
+            positions.push(unused);
+            let faces = (0..mesh.number_of_faces())
+                .map(|face| {
+                    mesh.face_vertices(face)
+                        .map(|vertex| vertex as u32)
+                        .collect()
+                })
+                .collect::<Vec<Vec<_>>>();
+            HalfEdgeMesh::from_polygons(positions, &faces).unwrap()
+        };
+        let cube = with_unused(&solids::cube_mesh());
+        let tetrahedron =
+            with_unused(&HalfEdgeMesh::from_geometry(&solids::tetrahedron(), 1e-5).unwrap());
+
+        let refined = [
+            (8, catmull_clark(&cube, &with_levels(2))),
+            (4, loop_subdivision(&tetrahedron, &with_levels(2))),
+        ];
+        for (index, refined) in refined.iter() {
+            assert_eq!(refined.positions[*index], unused);
+            for position in &refined.positions {
+                assert!(position.x.is_finite() && position.y.is_finite() && position.z.is_finite());
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use crate::raycast::Ray;
 use cgmath::Deg;
 use cgmath::Matrix;
 use cgmath::SquareMatrix;
@@ -6,12 +7,19 @@ use glium::Frame;
 use glium::Surface;
 
 pub fn transform_normals(total_transform: &Trans4d) -> Trans3d {
-    let without_projective_part = Trans3d::from_cols(
+    transform_tangents(total_transform)
+        .invert()
+        .unwrap()
+        .transpose()
+}
+
+/// Returns the linear part of `total_transform`, which maps tangents, in contrast to normals.
+pub fn transform_tangents(total_transform: &Trans4d) -> Trans3d {
+    Trans3d::from_cols(
         total_transform[0].truncate(),
         total_transform[1].truncate(),
         total_transform[2].truncate(),
-    );
-    without_projective_part.invert().unwrap().transpose()
+    )
 }
 
 pub fn reasonable_perspective(z_near: f32, z_far: f32) -> Trans4d {
@@ -27,3 +35,102 @@ pub fn fix_aspect_ratio_y(frame: &Frame, total_transform: Trans4d) -> Trans4d {
     let (width, height) = frame.get_dimensions();
     Trans4d::from_nonuniform_scale(1.0, width as f32 / height as f32, 1.0) * total_transform
 }
+
+/// Maps normalized device coordinates back to the space `total_transform` was applied to.
+///
+/// # Panics
+///
+/// Panics if `total_transform` is not invertible.
+pub fn unproject(total_transform: &Trans4d, device_coord: Coord3d) -> Coord3d {
+    let unprojected = total_transform.invert().unwrap() * device_coord.extend(1.0);
+    unprojected.truncate() / unprojected.w
+}
+
+/// Returns the ray through the cursor from the near to the far plane, in the space
+/// `total_transform` was applied to. `cursor_position` and `window_size` must be given in the
+/// same units, e.g. the logical pixels of `Navigator::mouse_position`.
+///
+/// For `total_transform = fix_aspect_ratio_y(frame, perspective * navigator.calculate_transform())`
+/// this is a world-space ray. Ray parameters 0 and 1 correspond to the near and far plane.
+///
+/// # Panics
+///
+/// Panics if `total_transform` is not invertible.
+pub fn cursor_ray(
+    cursor_position: (f64, f64),
+    window_size: (f64, f64),
+    total_transform: &Trans4d,
+) -> Ray {
+    let x = (2.0 * cursor_position.0 / window_size.0 - 1.0) as f32;
+    let y = (1.0 - 2.0 * cursor_position.1 / window_size.1) as f32;
+    let near = unproject(total_transform, Coord3d::new(x, y, -1.0));
+    let far = unproject(total_transform, Coord3d::new(x, y, 1.0));
+    Ray::new(near, far - near)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::navigator::Navigator;
+    use cgmath::InnerSpace;
+
+    fn project(total_transform: &Trans4d, point: Coord3d) -> Coord3d {
+        let clip = total_transform * point.extend(1.0);
+        clip.truncate() / clip.w
+    }
+
+    #[test]
+    fn window_center_unprojects_onto_the_view_axis() {
+        let perspective = reasonable_perspective(0.5, 50.0);
+        for &depth in [-1.0, -0.5, 0.0, 0.9, 1.0].iter() {
+            let point = unproject(&perspective, Coord3d::new(0.0, 0.0, depth));
+            assert!(point.x.abs() < 1e-5 && point.y.abs() < 1e-5, "{:?}", point);
+            assert!(point.z < 0.0);
+        }
+        let near = unproject(&perspective, Coord3d::new(0.0, 0.0, -1.0));
+        assert!((near.z + 0.5).abs() < 1e-5);
+        let far = unproject(&perspective, Coord3d::new(0.0, 0.0, 1.0));
+        assert!((far.z + 50.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn window_corners_map_to_the_device_corners() {
+        let identity = Trans4d::identity();
+        let window_size = (800.0, 600.0);
+        let corners = [
+            ((0.0, 0.0), (-1.0, 1.0)),
+            ((800.0, 0.0), (1.0, 1.0)),
+            ((0.0, 600.0), (-1.0, -1.0)),
+            ((800.0, 600.0), (1.0, -1.0)),
+            ((400.0, 300.0), (0.0, 0.0)),
+        ];
+        for &(cursor_position, (x, y)) in corners.iter() {
+            let ray = cursor_ray(cursor_position, window_size, &identity);
+            assert_eq!(ray.origin, Coord3d::new(x, y, -1.0));
+            assert_eq!(ray.direction, Coord3d::new(0.0, 0.0, 2.0));
+        }
+    }
+
+    #[test]
+    fn cursor_ray_passes_through_the_drawn_point() {
+        let navigator = Navigator::default();
+        let total_transform = reasonable_perspective(0.1, 100.0) * navigator.calculate_transform();
+        let window_size = (640.0, 480.0);
+        let point = Coord3d::new(0.3, -0.2, 0.5);
+
+        let device = project(&total_transform, point);
+        let cursor_position = (
+            (device.x as f64 + 1.0) / 2.0 * window_size.0,
+            (1.0 - device.y as f64) / 2.0 * window_size.1,
+        );
+        let ray = cursor_ray(cursor_position, window_size, &total_transform);
+
+        let offset = point - ray.origin;
+        let distance = offset.cross(ray.direction).magnitude() / ray.direction.magnitude();
+        assert!(distance < 1e-4, "{}", distance);
+        assert!((project(&total_transform, ray.at(0.0)).z + 1.0).abs() < 1e-4);
+        assert!((project(&total_transform, ray.at(1.0)).z - 1.0).abs() < 1e-4);
+        let parameter = offset.dot(ray.direction) / ray.direction.magnitude2();
+        assert!(parameter > 0.0 && parameter < 1.0);
+    }
+}
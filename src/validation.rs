@@ -0,0 +1,505 @@
+use crate::geometry::face_normal_and_area;
+use crate::geometry::weld_positions;
+use crate::geometry::Geometry;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use glium::index::PrimitiveType;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A problem found by `validate`. Triangles are referred to by their index in the triangle list,
+/// edges by the index of their first corner within the triangle.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MeshIssue {
+    /// The triangle has (almost) zero area. Its normal cannot be computed reliably.
+    DegenerateTriangle { triangle: usize },
+    /// A corner normal contains NaN or infinite components, as produced by
+    /// `Geometry::push_triangle` for degenerate triangles.
+    NonFiniteNormal { triangle: usize, corner: usize },
+    /// The edge belongs to only one triangle, so the surface is not watertight.
+    BoundaryEdge { triangle: usize, edge: usize },
+    /// The edge is shared by more than two triangles.
+    NonManifoldEdge { triangles: Vec<usize> },
+    /// Two neighboring triangles traverse their shared edge in the same direction, i.e. one of
+    /// them is wound the wrong way.
+    InconsistentOrientation { triangles: [usize; 2] },
+    /// The closed, consistently oriented surface formed by these triangles encloses a negative
+    /// volume, i.e. all of its triangles face inwards.
+    InsideOut { triangles: Vec<usize> },
+    /// The vertex normals of the triangle point away from the side its winding order faces.
+    FlippedNormals { triangle: usize },
+    /// Both triangles have the same corners, possibly in a different order.
+    DuplicateTriangle { triangles: [usize; 2] },
+}
+
+impl fmt::Display for MeshIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshIssue::DegenerateTriangle { triangle } => {
+                write!(f, "Triangle {} is degenerate", triangle)
+            }
+            MeshIssue::NonFiniteNormal { triangle, corner } => write!(
+                f,
+                "Corner {} of triangle {} has a non-finite normal",
+                corner, triangle
+            ),
+            MeshIssue::BoundaryEdge { triangle, edge } => write!(
+                f,
+                "Edge {} of triangle {} lies on an open boundary",
+                edge, triangle
+            ),
+            MeshIssue::NonManifoldEdge { triangles } => {
+                write!(f, "Triangles {:?} share a single edge", triangles)
+            }
+            MeshIssue::InconsistentOrientation { triangles } => write!(
+                f,
+                "Triangles {} and {} have opposite orientations",
+                triangles[0], triangles[1]
+            ),
+            MeshIssue::InsideOut { triangles } => write!(
+                f,
+                "The closed surface of {} triangles starting at triangle {} is inside out",
+                triangles.len(),
+                triangles[0]
+            ),
+            MeshIssue::FlippedNormals { triangle } => write!(
+                f,
+                "The normals of triangle {} oppose its winding order",
+                triangle
+            ),
+            MeshIssue::DuplicateTriangle { triangles } => write!(
+                f,
+                "Triangle {} duplicates triangle {}",
+                triangles[1], triangles[0]
+            ),
+        }
+    }
+}
+
+pub struct ValidationReport {
+    pub issues: Vec<MeshIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Returns whether every edge is shared by exactly two triangles.
+    pub fn is_watertight(&self) -> bool {
+        !self.issues.iter().any(|issue| {
+            matches!(
+                issue,
+                MeshIssue::BoundaryEdge { .. } | MeshIssue::NonManifoldEdge { .. }
+            )
+        })
+    }
+
+    /// Returns whether all triangles agree with their neighbors and face outwards.
+    pub fn is_consistently_oriented(&self) -> bool {
+        !self.issues.iter().any(|issue| {
+            matches!(
+                issue,
+                MeshIssue::InconsistentOrientation { .. } | MeshIssue::InsideOut { .. }
+            )
+        })
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.issues.is_empty() {
+            return write!(f, "No issues found");
+        }
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks a triangle list for problems that break rendering or mesh processing. Corners at most
+/// `tolerance` apart are considered to be the same vertex. Triangles collapsing to a line or point
+/// by this are only reported as degenerate and are not taken into account otherwise.
+///
+/// # Panics
+///
+/// Panics if `geometry` is not a `TrianglesList`.
+pub fn validate<V: MeshVertex>(geometry: &Geometry<V>, tolerance: f32) -> ValidationReport {
+    assert_eq!(
+        geometry.primitive_type,
+        PrimitiveType::TrianglesList,
+        "Only triangle lists can be validated"
+    );
+
+    let mut issues = Vec::new();
+    let triangles = geometry.vertices.chunks_exact(3).collect::<Vec<_>>();
+    let (positions, position_indices) = weld_positions(
+        geometry.vertices.iter().map(MeshVertex::position),
+        tolerance,
+    );
+    let corners = position_indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect::<Vec<_>>();
+    let is_collapsed = |triangle: usize| {
+        let [a, b, c] = corners[triangle];
+        a == b || b == c || c == a
+    };
+
+    for (index, triangle) in triangles.iter().enumerate() {
+        let face_normal = face_normal_and_area(
+            triangle[0].position(),
+            triangle[1].position(),
+            triangle[2].position(),
+        )
+        .map(|(normal, _)| normal);
+        if face_normal.is_none() || is_collapsed(index) {
+            issues.push(MeshIssue::DegenerateTriangle { triangle: index });
+        }
+
+        let mut normal_sum = Coord3d::new(0.0, 0.0, 0.0);
+        for (corner, vertex) in triangle.iter().enumerate() {
+            let normal = vertex.normal();
+            if normal.x.is_finite() && normal.y.is_finite() && normal.z.is_finite() {
+                normal_sum += normal;
+            } else {
+                issues.push(MeshIssue::NonFiniteNormal {
+                    triangle: index,
+                    corner,
+                });
+            }
+        }
+        if let Some(face_normal) = face_normal {
+            if normal_sum.dot(face_normal) < 0.0 {
+                issues.push(MeshIssue::FlippedNormals { triangle: index });
+            }
+        }
+    }
+
+    let mut sorted_corners = HashMap::new();
+    let mut directed_edges = HashMap::<_, Vec<_>>::new();
+    for (triangle, &[a, b, c]) in corners.iter().enumerate() {
+        if is_collapsed(triangle) {
+            continue;
+        }
+        let mut key = [a, b, c];
+        key.sort_unstable();
+        if let Some(&original) = sorted_corners.get(&key) {
+            issues.push(MeshIssue::DuplicateTriangle {
+                triangles: [original, triangle],
+            });
+        } else {
+            sorted_corners.insert(key, triangle);
+        }
+        for (edge, &(from, to)) in [(a, b), (b, c), (c, a)].iter().enumerate() {
+            directed_edges
+                .entry((from, to))
+                .or_default()
+                .push((triangle, edge));
+        }
+    }
+
+    let mut components = UnionFind::new(triangles.len());
+    let mut is_in_closed_surface = vec![true; triangles.len()];
+    let mut edges = directed_edges.keys().cloned().collect::<Vec<_>>();
+    edges.sort_unstable();
+    for (from, to) in edges {
+        if from > to && directed_edges.contains_key(&(to, from)) {
+            continue;
+        }
+        let forward = &directed_edges[&(from, to)];
+        let backward = directed_edges
+            .get(&(to, from))
+            .map(Vec::as_slice)
+            .unwrap_or(&[]);
+        let sharing_triangles = forward
+            .iter()
+            .chain(backward)
+            .map(|&(triangle, _)| triangle)
+            .collect::<Vec<_>>();
+
+        for &triangle in &sharing_triangles[1..] {
+            components.union(sharing_triangles[0], triangle);
+        }
+        let is_regular = match (forward.len(), backward.len()) {
+            (1, 1) => true,
+            (1, 0) => {
+                let (triangle, edge) = forward[0];
+                issues.push(MeshIssue::BoundaryEdge { triangle, edge });
+                false
+            }
+            (2, 0) => {
+                issues.push(MeshIssue::InconsistentOrientation {
+                    triangles: [sharing_triangles[0], sharing_triangles[1]],
+                });
+                false
+            }
+            _ => {
+                issues.push(MeshIssue::NonManifoldEdge {
+                    triangles: sharing_triangles.clone(),
+                });
+                false
+            }
+        };
+        if !is_regular {
+            for &triangle in &sharing_triangles {
+                is_in_closed_surface[triangle] = false;
+            }
+        }
+    }
+
+    let mut surfaces = HashMap::<_, Vec<_>>::new();
+    for triangle in (0..triangles.len()).filter(|&triangle| !is_collapsed(triangle)) {
+        surfaces
+            .entry(components.find(triangle))
+            .or_default()
+            .push(triangle);
+    }
+    let mut surfaces = surfaces.into_values().collect::<Vec<_>>();
+    surfaces.sort_unstable();
+    for surface in surfaces {
+        if !surface
+            .iter()
+            .all(|&triangle| is_in_closed_surface[triangle])
+        {
+            continue;
+        }
+        let volume = surface
+            .iter()
+            .map(|&triangle| {
+                let [a, b, c] = corners[triangle];
+                let (v1, v2, v3) = (
+                    positions[a as usize],
+                    positions[b as usize],
+                    positions[c as usize],
+                );
+                v1.dot(v2.cross(v3))
+            })
+            .sum::<f32>();
+        if volume < 0.0 {
+            issues.push(MeshIssue::InsideOut { triangles: surface });
+        }
+    }
+
+    ValidationReport { issues }
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(size: usize) -> UnionFind {
+        UnionFind {
+            parents: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, element: usize) -> usize {
+        let mut root = element;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        let mut current = element;
+        while self.parents[current] != root {
+            let parent = self.parents[current];
+            self.parents[current] = root;
+            current = parent;
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (root_a, root_b) = (self.find(a), self.find(b));
+        self.parents[root_a.max(root_b)] = root_a.min(root_b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solids;
+
+    const TOLERANCE: f32 = 1e-5;
+
+    fn triangle(geometry: &Geometry, index: usize) -> Vec<VertexAttribute> {
+        geometry.vertices[3 * index..3 * index + 3].to_vec()
+    }
+
+    fn reversed(geometry: &Geometry) -> Geometry {
+        let mut reversed = geometry.clone();
+        for triangle in reversed.vertices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+            for vertex in triangle {
+                *vertex = VertexAttribute::new(vertex.position(), -vertex.normal());
+            }
+        }
+        reversed
+    }
+
+    #[test]
+    fn closed_solids_have_no_issues() {
+        for solid in [solids::tetrahedron(), solids::cube()].iter() {
+            let report = validate(solid, TOLERANCE);
+            assert!(report.is_ok(), "{}", report);
+            assert!(report.is_watertight());
+            assert!(report.is_consistently_oriented());
+        }
+    }
+
+    #[test]
+    fn reports_degenerate_triangles() {
+        let mut cube = solids::cube();
+        let normal = Coord3d::new(0.0, 0.0, 1.0);
+        for &x in [0.0, 1.0, 2.0].iter() {
+            cube.vertices
+                .push(VertexAttribute::new(Coord3d::new(x, 5.0, 0.0), normal));
+        }
+
+        // Only collapsed triangles are left out of the edge checks, collinear ones are not.
+        let report = validate(&cube, TOLERANCE);
+        assert_eq!(
+            report.issues[0],
+            MeshIssue::DegenerateTriangle { triangle: 12 }
+        );
+        assert_eq!(report.issues.len(), 4);
+
+        let mut collapsed = solids::cube();
+        let corner = collapsed.vertices[0];
+        collapsed.vertices.extend(vec![corner; 3]);
+        let report = validate(&collapsed, TOLERANCE);
+        assert_eq!(
+            report.issues,
+            [MeshIssue::DegenerateTriangle { triangle: 12 }]
+        );
+    }
+
+    #[test]
+    fn reports_non_finite_normals() {
+        let mut cube = solids::cube();
+        let position = cube.vertices[4].position();
+        cube.vertices[4] = VertexAttribute::new(position, Coord3d::new(f32::NAN, 0.0, 0.0));
+
+        let report = validate(&cube, TOLERANCE);
+        assert_eq!(
+            report.issues,
+            [MeshIssue::NonFiniteNormal {
+                triangle: 1,
+                corner: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn reports_boundary_edges() {
+        let mut cube = solids::cube();
+        cube.vertices.truncate(33);
+
+        let report = validate(&cube, TOLERANCE);
+        assert_eq!(report.issues.len(), 3);
+        assert!(report
+            .issues
+            .iter()
+            .all(|issue| matches!(issue, MeshIssue::BoundaryEdge { .. })));
+        assert!(!report.is_watertight());
+        assert!(report.is_consistently_oriented());
+    }
+
+    #[test]
+    fn reports_non_manifold_edges() {
+        let mut cube = solids::cube();
+        let first = triangle(&cube, 0);
+        let mut fin = Geometry::new(PrimitiveType::TrianglesList);
+        fin.push_triangle(
+            first[0].position(),
+            first[1].position(),
+            Coord3d::new(0.0, 3.0, 3.0),
+        );
+        cube.append(&fin);
+
+        let report = validate(&cube, TOLERANCE);
+        assert!(report.issues.contains(&MeshIssue::NonManifoldEdge {
+            triangles: vec![0, 12, 8]
+        }));
+        assert!(!report.is_watertight());
+    }
+
+    #[test]
+    fn reports_inconsistent_orientation_and_flipped_normals() {
+        let mut cube = solids::cube();
+        cube.vertices.swap(0, 1);
+
+        let report = validate(&cube, TOLERANCE);
+        assert!(report
+            .issues
+            .contains(&MeshIssue::FlippedNormals { triangle: 0 }));
+        let inconsistent = report
+            .issues
+            .iter()
+            .filter(|issue| matches!(issue, MeshIssue::InconsistentOrientation { .. }))
+            .count();
+        assert_eq!(inconsistent, 3);
+        assert!(!report.is_consistently_oriented());
+        assert!(report.is_watertight());
+    }
+
+    #[test]
+    fn reports_flipped_normals() {
+        let mut cube = solids::cube();
+        for vertex in &mut cube.vertices[6..9] {
+            *vertex = VertexAttribute::new(vertex.position(), -vertex.normal());
+        }
+
+        let report = validate(&cube, TOLERANCE);
+        assert_eq!(report.issues, [MeshIssue::FlippedNormals { triangle: 2 }]);
+    }
+
+    #[test]
+    fn reports_duplicate_triangles() {
+        let mut cube = solids::cube();
+        let mut duplicate = triangle(&cube, 5);
+        duplicate.rotate_left(1);
+        cube.vertices.extend(duplicate);
+
+        let report = validate(&cube, TOLERANCE);
+        assert!(report
+            .issues
+            .contains(&MeshIssue::DuplicateTriangle { triangles: [5, 12] }));
+    }
+
+    #[test]
+    fn reports_inside_out_surfaces_separately() {
+        let mut cubes = solids::cube();
+        let mut inside_out = reversed(&solids::cube());
+        inside_out.transform(&Trans4d::from_translation(Coord3d::new(5.0, 0.0, 0.0)));
+        cubes.append(&inside_out);
+
+        let report = validate(&cubes, TOLERANCE);
+        assert_eq!(
+            report.issues,
+            [MeshIssue::InsideOut {
+                triangles: (12..24).collect()
+            }]
+        );
+        assert!(!report.is_consistently_oriented());
+        assert!(report.is_watertight());
+    }
+
+    #[test]
+    fn union_find_counts_components() {
+        let mut components = UnionFind::new(6);
+        components.union(0, 1);
+        components.union(4, 1);
+        components.union(2, 3);
+
+        let mut roots = (0..6)
+            .map(|element| components.find(element))
+            .collect::<Vec<_>>();
+        assert_eq!(roots, [0, 0, 2, 2, 0, 5]);
+        roots.dedup();
+        roots.sort_unstable();
+        roots.dedup();
+        assert_eq!(roots.len(), 3);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::geometry::face_normal_and_area;
+use crate::geometry::weld_positions;
+use crate::geometry::Geometry;
+use crate::manifold::Manifold2d;
+use crate::prelude::*;
+use cgmath::InnerSpace;
+use cgmath::Rad;
+use glium::index::PrimitiveType;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+#[derive(Copy, Clone, Debug)]
+pub enum EdgeSelection {
+    All,
+    /// Edges used by a single triangle.
+    Boundary,
+    /// Edges whose two triangles meet at a dihedral angle larger than the given one, as well as
+    /// boundary edges and edges shared by more than two triangles.
+    Feature(Rad<f32>),
+}
+
+/// Extracts the unique edges of a `TrianglesList` as a `LinesList`. Corners at most `tolerance`
+/// apart count as the same vertex. The lines have zero normals, see `Geometry::from_lines`.
+///
+/// # Panics
+///
+/// Panics if `geometry` is not a `TrianglesList`.
+pub fn from_geometry<V: MeshVertex>(
+    geometry: &Geometry<V>,
+    selection: EdgeSelection,
+    tolerance: f32,
+) -> Geometry {
+    assert_eq!(
+        geometry.primitive_type,
+        PrimitiveType::TrianglesList,
+        "Wireframes can only be extracted from triangle lists"
+    );
+    let (positions, indices) = weld_positions(
+        geometry.vertices.iter().map(MeshVertex::position),
+        tolerance,
+    );
+
+    // The triangles of every edge, in the order the edges first appear.
+    let mut edges = Vec::new();
+    let mut edge_triangles = HashMap::new();
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for corner in 0..3 {
+            let (a, b) = (corners[corner], corners[(corner + 1) % 3]);
+            if a == b {
+                continue;
+            }
+            let edge = (a.min(b), a.max(b));
+            edge_triangles
+                .entry(edge)
+                .or_insert_with(|| {
+                    edges.push(edge);
+                    Vec::new()
+                })
+                .push(triangle);
+        }
+    }
+
+    let face_normal = |triangle: usize| {
+        let vertices = &geometry.vertices[3 * triangle..3 * triangle + 3];
+        face_normal_and_area(
+            vertices[0].position(),
+            vertices[1].position(),
+            vertices[2].position(),
+        )
+        .map(|(normal, _)| normal)
+    };
+    let is_selected = |triangles: &[usize]| match selection {
+        EdgeSelection::All => true,
+        EdgeSelection::Boundary => triangles.len() == 1,
+        EdgeSelection::Feature(angle) => match triangles {
+            &[first, second] => match (face_normal(first), face_normal(second)) {
+                (Some(first), Some(second)) => first.dot(second) < angle.0.cos(),
+                _ => false,
+            },
+            _ => true,
+        },
+    };
+
+    lines(
+        &positions,
+        edges
+            .into_iter()
+            .filter(|edge| is_selected(&edge_triangles[edge])),
+    )
+}
+
+/// Returns the grid lines of a manifold as a `LinesList`, i.e. the edges of the quads that
+/// `Geometry::from_manifold` splits into triangles. Grid points at most `tolerance` apart, e.g. at
+/// the seam and the poles of `manifold::sphere`, count as the same point, so that every line
+/// appears once and collapsed lines are dropped.
+pub fn from_manifold<M: Manifold2d<Coord = Coord3d>>(coordinates: M, tolerance: f32) -> Geometry {
+    let (width, height) = (coordinates.width(), coordinates.height());
+    let (positions, indices) = weld_positions(
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| coordinates.get(x, y)),
+        tolerance,
+    );
+    let index = |x: usize, y: usize| indices[x + y * width];
+
+    let mut edges = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if x + 1 < width {
+                edges.push((index(x, y), index(x + 1, y)));
+            }
+            if y + 1 < height {
+                edges.push((index(x, y), index(x, y + 1)));
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    lines(
+        &positions,
+        edges
+            .into_iter()
+            .filter(|&(a, b)| a != b)
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .filter(|&edge| seen.insert(edge)),
+    )
+}
+
+fn lines<I: IntoIterator<Item = (u32, u32)>>(positions: &[Coord3d], edges: I) -> Geometry {
+    Geometry::from_lines(
+        edges
+            .into_iter()
+            .map(|(a, b)| (positions[a as usize], positions[b as usize])),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifold;
+    use crate::solids;
+    use cgmath::Deg;
+
+    const TOLERANCE: f32 = 1e-6;
+
+    fn line_count(lines: &Geometry) -> usize {
+        assert_eq!(lines.primitive_type, PrimitiveType::LinesList);
+        for vertex in &lines.vertices {
+            assert_eq!(vertex.normal(), Coord3d::new(0.0, 0.0, 0.0));
+        }
+        lines.vertices.len() / 2
+    }
+
+    #[test]
+    fn cube_edges() {
+        let cube = solids::cube();
+        // Twelve cube edges and a diagonal on each face.
+        let all = from_geometry(&cube, EdgeSelection::All, TOLERANCE);
+        assert_eq!(line_count(&all), 18);
+        let boundary = from_geometry(&cube, EdgeSelection::Boundary, TOLERANCE);
+        assert_eq!(line_count(&boundary), 0);
+        let features = from_geometry(&cube, EdgeSelection::Feature(Deg(30.0).into()), TOLERANCE);
+        assert_eq!(line_count(&features), 12);
+    }
+
+    #[test]
+    fn open_edges_are_boundary_and_feature_edges() {
+        let mut triangle = Geometry::new(PrimitiveType::TrianglesList);
+        triangle.push_triangle(
+            Coord3d::new(0.0, 0.0, 0.0),
+            Coord3d::new(1.0, 0.0, 0.0),
+            Coord3d::new(0.0, 1.0, 0.0),
+        );
+        for &selection in [
+            EdgeSelection::All,
+            EdgeSelection::Boundary,
+            EdgeSelection::Feature(Deg(30.0).into()),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                line_count(&from_geometry(&triangle, selection, TOLERANCE)),
+                3
+            );
+        }
+    }
+
+    #[test]
+    fn sphere_grid_lines_skip_the_seam_and_poles() {
+        let lines = from_manifold(manifold::sphere(8, 4), TOLERANCE);
+        // Three rings of latitude and eight meridians with four segments each.
+        assert_eq!(line_count(&lines), 3 * 8 + 8 * 4);
+    }
+}